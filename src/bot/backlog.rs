@@ -0,0 +1,92 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tracing::{debug, warn};
+
+use crate::discord::gateway::RawEvent;
+
+// Payloads a shard reads ahead of its processor before it stops reading.
+const BACKLOG_SIZE: usize = 1024;
+
+// Payloads read by a shard wait here for its processor. Once it is full the
+// reader waits too, so slow handlers hold up the socket instead of memory
+// growing, while heartbeats keep going from their own task.
+pub(crate) struct Backlog {
+    sender: Sender<RawEvent>,
+    recver: Mutex<Option<Receiver<RawEvent>>>,
+    behind: AtomicBool,
+}
+
+impl Default for Backlog {
+    fn default() -> Self {
+        Self::with_capacity(BACKLOG_SIZE)
+    }
+}
+
+impl Backlog {
+    fn with_capacity(capacity: usize) -> Self {
+        let (sender, recver) = mpsc::channel(capacity);
+        Self {
+            sender,
+            recver: Mutex::new(Some(recver)),
+            behind: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) async fn push(&self, raw: RawEvent) {
+        let raw = match self.sender.try_send(raw) {
+            Ok(()) => {
+                if self.behind.swap(false, Ordering::Relaxed) {
+                    debug!("Handlers caught up with the gateway");
+                }
+                return;
+            }
+            Err(TrySendError::Full(raw)) => raw,
+            // The processor only stops together with the shard.
+            Err(TrySendError::Closed(_)) => return,
+        };
+        if !self.behind.swap(true, Ordering::Relaxed) {
+            warn!(
+                backlog = self.sender.max_capacity(),
+                "Handlers are falling behind the gateway, waiting before reading on"
+            );
+        }
+        let _ = self.sender.send(raw).await;
+    }
+
+    pub(crate) fn take(&self) -> Option<Receiver<RawEvent>> {
+        self.recver.lock().expect("Should not be poisoned").take()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use serde_json::Value;
+
+    use super::Backlog;
+    use crate::discord::gateway::RawEvent;
+
+    #[tokio::test(start_paused = true)]
+    async fn full_backlog_waits() {
+        let backlog = Backlog::with_capacity(1);
+        let mut recver = backlog.take().expect("Should be taken once");
+        backlog.push(RawEvent::new(11, Value::Null)).await;
+
+        let push = backlog.push(RawEvent::new(11, Value::Null));
+        tokio::pin!(push);
+        let waited = tokio::time::timeout(Duration::from_secs(1), &mut push).await;
+        assert!(waited.is_err(), "Should wait for the processor");
+
+        recver.recv().await.expect("Should be pushed");
+        push.await;
+        recver
+            .recv()
+            .await
+            .expect("Should be pushed once there was room");
+    }
+}
@@ -0,0 +1,655 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock, RwLockWriteGuard,
+    },
+};
+
+use serde_json::Value;
+use tracing::debug;
+
+use super::client::DiscordClient;
+use crate::{
+    discord::{
+        gateway::RawEvent, Channel, Guild, Member, Message, RawChannel, RawGuild, RawMember,
+        RawMessage, RawRole, RawUser, Role, User,
+    },
+    prelude::*,
+};
+
+pub(crate) const DEFAULT_MESSAGE_LIMIT: usize = 100;
+
+#[derive(Debug, Default)]
+struct Entities {
+    me: Option<RawUser>,
+    guilds: HashMap<OwnedID, RawGuild>,
+    channels: HashMap<OwnedID, RawChannel>,
+    roles: HashMap<OwnedID, HashMap<OwnedID, RawRole>>,
+    members: HashMap<OwnedID, HashMap<OwnedID, RawMember>>,
+    users: HashMap<OwnedID, RawUser>,
+    messages: HashMap<OwnedID, VecDeque<RawMessage>>,
+}
+
+impl Entities {
+    fn insert_user(&mut self, user: &RawUser) {
+        if self.me.as_ref().is_some_and(|me| me.id == user.id) {
+            self.me = Some(user.clone());
+        }
+        self.users.insert(user.id.clone(), user.clone());
+    }
+
+    fn insert_member(&mut self, guild_id: &ID, member: RawMember) {
+        let Some(user) = &member.user else {
+            return;
+        };
+        self.insert_user(user);
+        let user_id = user.id.clone();
+        self.members
+            .entry(guild_id.to_owned())
+            .or_default()
+            .insert(user_id, member);
+    }
+
+    fn insert_channel(&mut self, guild_id: Option<&ID>, mut channel: RawChannel) {
+        if channel.guild_id.is_none() {
+            channel.guild_id = guild_id.map(ToOwned::to_owned);
+        }
+        self.channels.insert(channel.id.clone(), channel);
+    }
+
+    fn remove_channel(&mut self, channel_id: &ID) {
+        self.channels.remove(channel_id);
+        self.messages.remove(channel_id);
+    }
+
+    fn remove_guild(&mut self, guild_id: &ID) {
+        self.guilds.remove(guild_id);
+        self.roles.remove(guild_id);
+        self.members.remove(guild_id);
+        let channels = self
+            .channels
+            .values()
+            .filter(|channel| channel.guild_id.as_deref() == Some(guild_id))
+            .map(|channel| channel.id.clone())
+            .collect::<Vec<_>>();
+        for channel_id in channels {
+            self.remove_channel(&channel_id);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Ready {
+    user: RawUser,
+    #[serde(default)]
+    guilds: Vec<UnavailableGuild>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UnavailableGuild {
+    id: OwnedID,
+}
+
+#[derive(Debug, Deserialize)]
+struct GuildPayload {
+    #[serde(flatten)]
+    guild: RawGuild,
+    #[serde(default)]
+    roles: Option<Vec<RawRole>>,
+    #[serde(default)]
+    channels: Vec<RawChannel>,
+    #[serde(default)]
+    threads: Vec<RawChannel>,
+    #[serde(default)]
+    members: Vec<RawMember>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GuildDelete {
+    id: OwnedID,
+    #[serde(default)]
+    unavailable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GuildMembers {
+    guild_id: OwnedID,
+    #[serde(default)]
+    members: Vec<RawMember>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GuildMember {
+    guild_id: OwnedID,
+    #[serde(flatten)]
+    member: RawMember,
+}
+
+#[derive(Debug, Deserialize)]
+struct GuildMemberRemove {
+    guild_id: OwnedID,
+    user: RawUser,
+}
+
+#[derive(Debug, Deserialize)]
+struct GuildRole {
+    guild_id: OwnedID,
+    role: RawRole,
+}
+
+#[derive(Debug, Deserialize)]
+struct GuildRoleDelete {
+    guild_id: OwnedID,
+    role_id: OwnedID,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageUpdate {
+    id: OwnedID,
+    channel_id: OwnedID,
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageDelete {
+    #[serde(alias = "ids")]
+    id: OneOrMany,
+    channel_id: OwnedID,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(OwnedID),
+    Many(Vec<OwnedID>),
+}
+
+#[derive(Debug)]
+pub(crate) struct RawCache {
+    entities: RwLock<Entities>,
+    message_limit: AtomicUsize,
+}
+
+impl Default for RawCache {
+    fn default() -> Self {
+        Self {
+            entities: Default::default(),
+            message_limit: AtomicUsize::new(DEFAULT_MESSAGE_LIMIT),
+        }
+    }
+}
+
+impl RawCache {
+    #[inline]
+    pub(crate) fn set_message_limit(&self, limit: usize) {
+        self.message_limit.store(limit, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_me(&self, me: RawUser) {
+        let mut entities = self.write();
+        entities.users.insert(me.id.clone(), me.clone());
+        entities.me = Some(me);
+    }
+
+    pub(crate) fn insert_channel(&self, channel: RawChannel) {
+        self.write().insert_channel(None, channel);
+    }
+
+    // A payload the cache cannot make sense of is skipped, the event itself
+    // is still dispatched.
+    pub(crate) fn update(&self, raw: &RawEvent) {
+        let Some(event_name) = raw.event_name() else {
+            return;
+        };
+        if let Err(err) = self.apply(event_name, raw.data()) {
+            debug!(event = event_name, error = %err, "Cannot cache event");
+        }
+    }
+
+    #[inline]
+    fn write(&self) -> RwLockWriteGuard<'_, Entities> {
+        self.entities.write().expect("Should not be poisoned")
+    }
+
+    // Payloads are parsed before the lock is taken, and events the cache has
+    // no use for never take it at all.
+    fn apply(&self, event_name: &str, data: &Value) -> Result<(), serde_json::Error> {
+        match event_name {
+            "READY" => {
+                let Ready { user, guilds } = Ready::deserialize(data)?;
+                let mut entities = self.write();
+                entities.me = Some(user.clone());
+                entities.insert_user(&user);
+                // Only ids until each guild's GUILD_CREATE arrives.
+                for UnavailableGuild { id } in guilds {
+                    entities
+                        .guilds
+                        .entry(id.clone())
+                        .or_insert_with(|| RawGuild::unavailable(id));
+                }
+            }
+            "USER_UPDATE" => {
+                let user = RawUser::deserialize(data)?;
+                let mut entities = self.write();
+                entities.me = Some(user.clone());
+                entities.insert_user(&user);
+            }
+            "GUILD_CREATE" | "GUILD_UPDATE" => {
+                let payload = GuildPayload::deserialize(data)?;
+                let guild_id = payload.guild.id.clone();
+                let mut entities = self.write();
+                if let Some(roles) = payload.roles {
+                    let roles = roles
+                        .into_iter()
+                        .map(|role| (role.id.clone(), role))
+                        .collect();
+                    entities.roles.insert(guild_id.clone(), roles);
+                }
+                for channel in payload.channels.into_iter().chain(payload.threads) {
+                    entities.insert_channel(Some(&guild_id), channel);
+                }
+                for member in payload.members {
+                    entities.insert_member(&guild_id, member);
+                }
+                entities.guilds.insert(guild_id, payload.guild);
+            }
+            "GUILD_DELETE" => {
+                let GuildDelete { id, unavailable } = GuildDelete::deserialize(data)?;
+                let mut entities = self.write();
+                // An outage rather than a removal, the guild comes back with
+                // GUILD_CREATE once it is over.
+                if unavailable {
+                    if let Some(guild) = entities.guilds.get_mut(&id) {
+                        guild.unavailable = true;
+                    }
+                } else {
+                    entities.remove_guild(&id);
+                }
+            }
+            "CHANNEL_CREATE" | "CHANNEL_UPDATE" | "THREAD_CREATE" | "THREAD_UPDATE" => {
+                let channel = RawChannel::deserialize(data)?;
+                self.write().insert_channel(None, channel);
+            }
+            "CHANNEL_DELETE" | "THREAD_DELETE" => {
+                let channel_id = OwnedID::deserialize(&data["id"])?;
+                self.write().remove_channel(&channel_id);
+            }
+            "GUILD_ROLE_CREATE" | "GUILD_ROLE_UPDATE" => {
+                let GuildRole { guild_id, role } = GuildRole::deserialize(data)?;
+                self.write()
+                    .roles
+                    .entry(guild_id)
+                    .or_default()
+                    .insert(role.id.clone(), role);
+            }
+            "GUILD_ROLE_DELETE" => {
+                let GuildRoleDelete { guild_id, role_id } = GuildRoleDelete::deserialize(data)?;
+                if let Some(roles) = self.write().roles.get_mut(&guild_id) {
+                    roles.remove(&role_id);
+                }
+            }
+            "GUILD_MEMBER_ADD" | "GUILD_MEMBER_UPDATE" => {
+                let GuildMember { guild_id, member } = GuildMember::deserialize(data)?;
+                self.write().insert_member(&guild_id, member);
+            }
+            "GUILD_MEMBER_REMOVE" => {
+                let GuildMemberRemove { guild_id, user } = GuildMemberRemove::deserialize(data)?;
+                if let Some(members) = self.write().members.get_mut(&guild_id) {
+                    members.remove(&user.id);
+                }
+            }
+            "GUILD_MEMBERS_CHUNK" => {
+                let GuildMembers { guild_id, members } = GuildMembers::deserialize(data)?;
+                let mut entities = self.write();
+                for member in members {
+                    entities.insert_member(&guild_id, member);
+                }
+            }
+            "MESSAGE_CREATE" => {
+                let limit = self.message_limit.load(Ordering::Relaxed);
+                let message = RawMessage::deserialize(data)?;
+                let mut entities = self.write();
+                entities.insert_user(&message.author);
+                if limit == 0 {
+                    return Ok(());
+                }
+                let messages = entities
+                    .messages
+                    .entry(message.channel_id.clone())
+                    .or_default();
+                if messages.len() >= limit {
+                    messages.pop_front();
+                }
+                messages.push_back(message);
+            }
+            "MESSAGE_UPDATE" => {
+                let update = MessageUpdate::deserialize(data)?;
+                let mut entities = self.write();
+                let cached = entities
+                    .messages
+                    .get_mut(&update.channel_id)
+                    .and_then(|messages| messages.iter_mut().find(|msg| msg.id == update.id));
+                if let (Some(message), Some(content)) = (cached, update.content) {
+                    message.content = content;
+                }
+            }
+            "MESSAGE_DELETE" | "MESSAGE_DELETE_BULK" => {
+                let MessageDelete { id, channel_id } = MessageDelete::deserialize(data)?;
+                let ids = match id {
+                    OneOrMany::One(id) => vec![id],
+                    OneOrMany::Many(ids) => ids,
+                };
+                if let Some(messages) = self.write().messages.get_mut(&channel_id) {
+                    messages.retain(|msg| !ids.contains(&msg.id));
+                }
+            }
+            _ => return Ok(()),
+        }
+        Ok(())
+    }
+}
+
+pub struct Cache<'a> {
+    cache: &'a RawCache,
+    client: &'a DiscordClient,
+}
+
+impl<'a> Cache<'a> {
+    #[inline]
+    pub(crate) fn new(cache: &'a RawCache, client: &'a DiscordClient) -> Self {
+        Self { cache, client }
+    }
+
+    #[inline]
+    fn read<T>(&self, read: impl FnOnce(&Entities) -> T) -> T {
+        read(&self.cache.entities.read().expect("Should not be poisoned"))
+    }
+
+    pub fn me(&self) -> Option<User> {
+        self.read(|entities| entities.me.clone())
+            .map(|user| User::from_raw(user, self.client.clone()))
+    }
+
+    pub fn guild(&self, guild_id: &ID) -> Option<Guild> {
+        self.read(|entities| entities.guilds.get(guild_id).cloned())
+            .map(RawGuild::to_mature)
+    }
+
+    pub fn guilds(&self) -> Vec<Guild> {
+        self.read(|entities| entities.guilds.values().cloned().collect::<Vec<_>>())
+            .into_iter()
+            .map(RawGuild::to_mature)
+            .collect()
+    }
+
+    pub fn channel(&self, channel_id: &ID) -> Option<Channel> {
+        self.read(|entities| entities.channels.get(channel_id).cloned())
+            .map(|channel| Channel::from_raw(channel, self.client.clone()))
+    }
+
+    pub fn guild_channels(&self, guild_id: &ID) -> Vec<Channel> {
+        self.read(|entities| {
+            entities
+                .channels
+                .values()
+                .filter(|channel| channel.guild_id.as_deref() == Some(guild_id))
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+        .into_iter()
+        .map(|channel| Channel::from_raw(channel, self.client.clone()))
+        .collect()
+    }
+
+    pub fn role(&self, guild_id: &ID, role_id: &ID) -> Option<Role> {
+        self.read(|entities| entities.roles.get(guild_id)?.get(role_id).cloned())
+            .map(|role| role.to_mature(guild_id.to_owned()))
+    }
+
+    pub fn roles(&self, guild_id: &ID) -> Vec<Role> {
+        self.read(|entities| {
+            entities
+                .roles
+                .get(guild_id)
+                .map(|roles| roles.values().cloned().collect::<Vec<_>>())
+                .unwrap_or_default()
+        })
+        .into_iter()
+        .map(|role| role.to_mature(guild_id.to_owned()))
+        .collect()
+    }
+
+    pub fn member(&self, guild_id: &ID, user_id: &ID) -> Option<Member> {
+        self.read(|entities| entities.members.get(guild_id)?.get(user_id).cloned())
+            .map(|member| member.to_mature(self.client.clone()))
+    }
+
+    pub fn members(&self, guild_id: &ID) -> Vec<Member> {
+        self.read(|entities| {
+            entities
+                .members
+                .get(guild_id)
+                .map(|members| members.values().cloned().collect::<Vec<_>>())
+                .unwrap_or_default()
+        })
+        .into_iter()
+        .map(|member| member.to_mature(self.client.clone()))
+        .collect()
+    }
+
+    pub fn user(&self, user_id: &ID) -> Option<User> {
+        self.read(|entities| entities.users.get(user_id).cloned())
+            .map(|user| User::from_raw(user, self.client.clone()))
+    }
+
+    pub fn message(&self, channel_id: &ID, message_id: &ID) -> Option<Message> {
+        self.read(|entities| {
+            entities
+                .messages
+                .get(channel_id)?
+                .iter()
+                .find(|msg| *msg.id == *message_id)
+                .cloned()
+        })
+        .map(|msg| msg.to_mature(self.client.clone()))
+    }
+
+    // Oldest first, holding at most the configured number of messages.
+    pub fn messages(&self, channel_id: &ID) -> Vec<Message> {
+        self.read(|entities| {
+            entities
+                .messages
+                .get(channel_id)
+                .map(|messages| messages.iter().cloned().collect::<Vec<_>>())
+                .unwrap_or_default()
+        })
+        .into_iter()
+        .map(|msg| msg.to_mature(self.client.clone()))
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::error::Error;
+
+    use serde_json::json;
+
+    use super::RawCache;
+    use crate::{
+        bot::client::DiscordClient,
+        discord::{
+            gateway::{MessageCreatedEvent, RawEvent},
+            RestError, SendedMessage,
+        },
+        prelude::{Bot, BotImpl, Intents, ID},
+        testing::MockDiscord,
+    };
+
+    fn dispatch(cache: &RawCache, event_name: &str, data: serde_json::Value) {
+        let raw = serde_json::from_value::<RawEvent>(json!({
+            "op": 0,
+            "s": 1,
+            "t": event_name,
+            "d": data,
+        }))
+        .unwrap();
+        cache.update(&raw);
+    }
+
+    #[test]
+    fn ready_guilds() {
+        let cache = RawCache::default();
+        let client = DiscordClient::from_raw(reqwest::Client::new(), 10);
+        let view = super::Cache::new(&cache, &client);
+        dispatch(
+            &cache,
+            "READY",
+            json!({
+                "user": { "id": "1000", "username": "mili" },
+                "guilds": [{ "id": "1000", "unavailable": true }],
+            }),
+        );
+        let guilds = view.guilds();
+        assert_eq!(guilds.len(), 1);
+        assert!(guilds[0].unavailable());
+
+        dispatch(
+            &cache,
+            "GUILD_CREATE",
+            json!({ "id": "1000", "name": "guild" }),
+        );
+        let guild = view.guild(ID::from_raw("1000")).unwrap();
+        assert_eq!(guild.name(), "guild");
+        assert!(!guild.unavailable());
+    }
+
+    #[test]
+    fn guild_lifecycle() {
+        let cache = RawCache::default();
+        let client = DiscordClient::from_raw(reqwest::Client::new(), 10);
+        let view = super::Cache::new(&cache, &client);
+        dispatch(
+            &cache,
+            "GUILD_CREATE",
+            json!({
+                "id": "1000",
+                "name": "guild",
+                "roles": [{ "id": "1000", "name": "@everyone", "permissions": "0" }],
+                "channels": [{ "id": "2000", "name": "general" }],
+                "members": [{ "user": { "id": "3000", "username": "someone" }, "roles": [] }],
+            }),
+        );
+        let (guild_id, channel_id) = (ID::from_raw("1000"), ID::from_raw("2000"));
+        assert_eq!(view.guild(guild_id).unwrap().name(), "guild");
+        assert_eq!(view.channel(channel_id).unwrap().guild_id(), Some(guild_id));
+        assert_eq!(view.roles(guild_id).len(), 1);
+        assert!(view.member(guild_id, ID::from_raw("3000")).is_some());
+        assert_eq!(
+            view.user(ID::from_raw("3000")).unwrap().username(),
+            "someone"
+        );
+
+        cache.set_message_limit(1);
+        for (id, content) in [("4000", "first"), ("4001", "second")] {
+            dispatch(
+                &cache,
+                "MESSAGE_CREATE",
+                json!({
+                    "id": id,
+                    "channel_id": "2000",
+                    "author": { "id": "3000", "username": "someone" },
+                    "content": content,
+                    "tts": false,
+                }),
+            );
+        }
+        dispatch(
+            &cache,
+            "MESSAGE_UPDATE",
+            json!({ "id": "4001", "channel_id": "2000", "content": "edited" }),
+        );
+        let messages = view.messages(channel_id);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content(), Some("edited"));
+
+        dispatch(
+            &cache,
+            "GUILD_DELETE",
+            json!({ "id": "1000", "unavailable": true }),
+        );
+        assert!(view.guild(guild_id).unwrap().unavailable());
+        assert!(view.channel(channel_id).is_some());
+        assert!(view.member(guild_id, ID::from_raw("3000")).is_some());
+
+        dispatch(&cache, "GUILD_DELETE", json!({ "id": "1000" }));
+        assert!(view.guild(guild_id).is_none());
+        assert!(view.channel(channel_id).is_none());
+        assert!(view.messages(channel_id).is_empty());
+        assert!(view.member(guild_id, ID::from_raw("3000")).is_none());
+    }
+
+    struct Replier;
+
+    impl BotImpl for Replier {
+        type Error = RestError;
+
+        async fn on_message_created(
+            _: Bot<Self>,
+            msg: MessageCreatedEvent,
+        ) -> Result<(), RestError> {
+            msg.message
+                .channel()
+                .await?
+                .send(SendedMessage::plain("hello!"))
+                .await?;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn channel_from_cache() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        mock.rest
+            .route("POST", "/channels/2000/messages", 200, json!({}));
+
+        let bot = mock
+            .template()
+            .intents(Intents::GUILDS | Intents::MESSAGE_CONTENT | Intents::GUILD_MESSAGES)
+            .implement(Replier, mock.token());
+        let requests = mock
+            .run_until(bot, async {
+                mock.gateway.dispatch(
+                    "GUILD_CREATE",
+                    json!({
+                        "id": "1000",
+                        "name": "guild",
+                        "channels": [{ "id": "2000", "name": "general" }],
+                    }),
+                );
+                mock.gateway.dispatch(
+                    "MESSAGE_CREATE",
+                    json!({
+                        "id": "4000",
+                        "channel_id": "2000",
+                        "guild_id": "1000",
+                        "author": { "id": "3000", "username": "someone" },
+                        "content": "hi",
+                        "tts": false,
+                    }),
+                );
+                let mut requests = Vec::new();
+                loop {
+                    let request = mock.rest.next_request().await.expect("Should be running");
+                    if request.method.as_ref() == "POST" {
+                        return requests;
+                    }
+                    requests.push(request.path);
+                }
+            })
+            .await;
+        assert!(!requests.iter().any(|path| path.starts_with("/channels")));
+        Ok(())
+    }
+}
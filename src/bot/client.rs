@@ -1,29 +1,69 @@
-use std::fmt::Display;
+use std::{fmt::Display, sync::Arc};
 
 use reqwest::RequestBuilder;
+use serde::de::DeserializeOwned;
+use tracing::{debug, debug_span, warn, Instrument};
+
+use super::cache::{Cache, RawCache};
+use crate::{discord::RestError, metrics};
+
+pub const DEFAULT_API_URL: &str = "https://discord.com/api";
 
 #[derive(Debug, Clone)]
 pub struct DiscordClient {
     client: reqwest::Client,
+    api_url: Box<str>,
     api_version: u8,
+    cache: Arc<RawCache>,
 }
 
 impl DiscordClient {
     pub fn from_raw(client: reqwest::Client, api_version: u8) -> Self {
+        Self::with_api_url(client, DEFAULT_API_URL, api_version)
+    }
+
+    pub fn with_api_url(
+        client: reqwest::Client,
+        api_url: impl Into<Box<str>>,
+        api_version: u8,
+    ) -> Self {
+        let api_url: Box<str> = api_url.into();
         Self {
             client,
+            api_url: api_url.trim_end_matches('/').into(),
             api_version,
+            cache: Default::default(),
         }
     }
 
     fn api(&self, route: impl Display) -> String {
-        format!("https://discord.com/api/v{}/{route}", self.api_version)
+        let route = route.to_string();
+        format!(
+            "{}/v{}/{}",
+            self.api_url,
+            self.api_version,
+            route.trim_start_matches('/')
+        )
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.api_url
     }
 
     pub fn api_version(&self) -> u8 {
         self.api_version
     }
 
+    #[inline]
+    pub(crate) fn raw_cache(&self) -> &RawCache {
+        &self.cache
+    }
+
+    #[inline]
+    pub(crate) fn cache(&self) -> Cache<'_> {
+        Cache::new(&self.cache, self)
+    }
+
     pub fn get(&self, route: impl Display) -> RequestBuilder {
         self.client.get(self.api(route))
     }
@@ -31,4 +71,32 @@ impl DiscordClient {
     pub fn post(&self, route: impl Display) -> RequestBuilder {
         self.client.post(self.api(route))
     }
+
+    pub(crate) async fn execute(request: RequestBuilder) -> Result<String, RestError> {
+        let (client, request) = request.build_split();
+        let request = request?;
+        let (method, path) = (request.method().clone(), request.url().path().to_owned());
+        let span = debug_span!("rest", method = %method, route = path);
+        let response = client.execute(request).instrument(span.clone()).await?;
+        let status = response.status();
+        debug!(parent: &span, status = status.as_u16(), "Request finished");
+        metrics::rest_request(method.as_str(), &path, status.as_u16());
+        let body = response.text().await?;
+        if !status.is_success() {
+            warn!(parent: &span, status = status.as_u16(), body, "Request failed");
+            return Err(RestError::Status {
+                status: status.as_u16(),
+                body: body.into(),
+            });
+        }
+        Ok(body)
+    }
+
+    pub(crate) async fn fetch<T>(&self, route: impl Display) -> Result<T, RestError>
+    where
+        T: DeserializeOwned,
+    {
+        let body = Self::execute(self.get(route)).await?;
+        Ok(serde_json::from_str(&body)?)
+    }
 }
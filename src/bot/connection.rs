@@ -1,26 +1,196 @@
+use std::{error::Error as StdError, io};
+
+use flate2::{Decompress, FlushDecompress, Status};
 use futures::{
     stream::{SplitSink, SplitStream},
     SinkExt, StreamExt,
 };
-use tokio::sync::Mutex;
-use tokio_tungstenite::{connect_async, tungstenite::Error, tungstenite::Message};
+use tokio::sync::{watch, Mutex};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{
+        protocol::{frame::coding::CloseCode as WsCloseCode, CloseFrame},
+        Error, Message,
+    },
+};
+
+use super::send_queue::{Priority, SendQueue};
+use crate::discord::gateway::{etf, CloseCode, RawEvent};
 
 type Socket =
     tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
 
+const ZLIB_SUFFIX: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Compression {
+    #[default]
+    None,
+    ZlibStream,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    #[default]
+    Json,
+    Etf,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Etf => "etf",
+        }
+    }
+
+    pub(crate) fn encode(&self, event: &RawEvent) -> Message {
+        match self {
+            Self::Json => Message::Text(serde_json::to_string(event).expect("Should succeed")),
+            Self::Etf => {
+                let value = serde_json::to_value(event).expect("Should succeed");
+                Message::Binary(etf::encode(&value))
+            }
+        }
+    }
+
+    pub(crate) fn decode(
+        &self,
+        payload: &[u8],
+    ) -> Result<RawEvent, Box<dyn StdError + Send + Sync>> {
+        Ok(match self {
+            Self::Json => serde_json::from_slice(payload)?,
+            Self::Etf => serde_json::from_value(etf::decode(payload)?)?,
+        })
+    }
+}
+
+pub(crate) fn socket_url(
+    base: &str,
+    api_version: u8,
+    encoding: Encoding,
+    compression: Compression,
+) -> String {
+    let mut url = format!(
+        "{}/?v={}&encoding={}",
+        base.trim_end_matches('/'),
+        api_version,
+        encoding.as_str()
+    );
+    if compression == Compression::ZlibStream {
+        url.push_str("&compress=zlib-stream");
+    }
+    url
+}
+
+struct Inflater {
+    context: Decompress,
+    buffer: Vec<u8>,
+}
+
+impl Inflater {
+    fn new() -> Self {
+        Self {
+            context: Decompress::new(true),
+            buffer: Vec::new(),
+        }
+    }
+
+    // Returns `None` until a whole payload, which always ends with the zlib
+    // sync flush suffix, has been received.
+    fn inflate(&mut self, data: &[u8]) -> Option<Result<Vec<u8>, Error>> {
+        self.buffer.extend_from_slice(data);
+        if !self.buffer.ends_with(&ZLIB_SUFFIX) {
+            return None;
+        }
+
+        let mut input = self.buffer.as_slice();
+        let mut output = Vec::with_capacity(input.len() * 4);
+        loop {
+            let (total_in, total_out) = (self.context.total_in(), self.context.total_out());
+            let status =
+                match self
+                    .context
+                    .decompress_vec(input, &mut output, FlushDecompress::Sync)
+                {
+                    Ok(status) => status,
+                    Err(err) => {
+                        self.buffer.clear();
+                        return Some(Err(io::Error::new(io::ErrorKind::InvalidData, err).into()));
+                    }
+                };
+            let consumed = (self.context.total_in() - total_in) as usize;
+            let produced = self.context.total_out() - total_out;
+            input = &input[consumed..];
+            match status {
+                // There was room for output, so another round would be stuck
+                // on the same input forever.
+                _ if consumed == 0 && produced == 0 && !input.is_empty() => {
+                    self.buffer.clear();
+                    let err = format!("Inflating made no progress ({:?})", status);
+                    return Some(Err(io::Error::new(io::ErrorKind::InvalidData, err).into()));
+                }
+                Status::StreamEnd => break,
+                Status::Ok | Status::BufError => {}
+            }
+            if input.is_empty() && output.len() < output.capacity() {
+                break;
+            }
+            output.reserve(output.capacity());
+        }
+        self.buffer.clear();
+        Some(Ok(output))
+    }
+}
+
+struct Receiver {
+    stream: SplitStream<Socket>,
+    inflater: Option<Inflater>,
+}
+
+impl Receiver {
+    fn new(stream: SplitStream<Socket>, compression: Compression) -> Self {
+        Self {
+            stream,
+            inflater: (compression == Compression::ZlibStream).then(Inflater::new),
+        }
+    }
+}
+
+pub(crate) enum Received {
+    Payload(Vec<u8>),
+    Closed(Option<CloseCode>),
+    Failed(Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Session {
+    // Connected, or about to be, without having identified or resumed yet.
+    Pending,
+    Open,
+    Closed,
+}
+
 pub struct Connection {
-    sender: Mutex<SplitSink<Socket, Message>>,
-    recver: Mutex<SplitStream<Socket>>,
+    sender: Mutex<Option<SplitSink<Socket, Message>>>,
+    recver: Mutex<Option<Receiver>>,
+    queue: SendQueue,
+    // Only changed while holding the sender lock.
+    session: watch::Sender<Session>,
+    compression: Compression,
 }
 
 impl Connection {
-    pub async fn new(socket_url: &str) -> Result<Self, Error> {
-        let (socket, _) = connect_async(socket_url).await?;
-        let (sender, recver) = socket.split();
-        Ok(Self {
-            sender: Mutex::new(sender),
-            recver: Mutex::new(recver),
-        })
+    // Starts without a socket, the shard opens one once it is allowed to
+    // identify.
+    pub fn new(compression: Compression) -> Self {
+        Self {
+            sender: Mutex::new(None),
+            recver: Mutex::new(None),
+            queue: SendQueue::default(),
+            session: watch::Sender::new(Session::Pending),
+            compression,
+        }
     }
 
     pub async fn change_socket(&self, socket_url: &str) -> Result<(), Error> {
@@ -29,17 +199,159 @@ impl Connection {
             self.recver.lock(),
             connect_async(socket_url)
         );
+        self.session.send_replace(Session::Pending);
         let (socket, _) = res?;
-        (*ori_sender, *ori_recver) = socket.split();
+        let (sender, recver) = socket.split();
+        *ori_sender = Some(sender);
+        *ori_recver = Some(Receiver::new(recver, self.compression));
+        self.queue.reset();
         Ok(())
     }
 
-    pub async fn send(&self, msg: String) -> Result<(), Error> {
-        self.sender.lock().await.send(Message::Text(msg)).await
+    // Discord closes a connection with 4003 when anything but heartbeats
+    // arrives ahead of identify or resume, so normal payloads wait for those.
+    pub(crate) async fn send(&self, msg: Message, priority: Priority) -> Result<(), Error> {
+        self.queue.acquire(priority).await;
+        let mut session = self.session.subscribe();
+        loop {
+            let mut sender = self.sender.lock().await;
+            let state = *session.borrow_and_update();
+            match state {
+                Session::Closed => return Err(Error::ConnectionClosed),
+                Session::Pending if priority == Priority::Normal => {}
+                _ => {
+                    let Some(sender) = &mut *sender else {
+                        return Err(Error::ConnectionClosed);
+                    };
+                    sender.send(msg).await?;
+                    if priority == Priority::Identify {
+                        self.session.send_replace(Session::Open);
+                    }
+                    return Ok(());
+                }
+            }
+            drop(sender);
+            let _ = session.changed().await;
+        }
     }
 
-    pub async fn recv(&self) -> Option<Result<String, Error>> {
-        let msg = self.recver.lock().await.next().await;
-        msg.map(|res| res.and_then(Message::into_text))
+    // Sends still waiting for a session fail once the connection is closed.
+    pub(crate) async fn close(&self) -> Result<(), Error> {
+        let frame = CloseFrame {
+            code: WsCloseCode::Normal,
+            reason: "".into(),
+        };
+        let mut sender = self.sender.lock().await;
+        self.session.send_replace(Session::Closed);
+        match &mut *sender {
+            Some(sender) => sender.send(Message::Close(Some(frame))).await,
+            None => Err(Error::ConnectionClosed),
+        }
+    }
+
+    pub(crate) async fn recv(&self) -> Received {
+        let mut recver = self.recver.lock().await;
+        let Some(Receiver { stream, inflater }) = &mut *recver else {
+            return Received::Closed(None);
+        };
+        loop {
+            let Some(msg) = stream.next().await else {
+                return Received::Closed(None);
+            };
+            match msg {
+                Ok(Message::Text(text)) => return Received::Payload(text.into_bytes()),
+                Ok(Message::Binary(data)) => match inflater {
+                    Some(inflater) => match inflater.inflate(&data) {
+                        Some(Ok(inflated)) => return Received::Payload(inflated),
+                        Some(Err(err)) => return Received::Failed(err),
+                        None => continue,
+                    },
+                    None => return Received::Payload(data),
+                },
+                Ok(Message::Close(frame)) => {
+                    return Received::Closed(frame.map(|frame| u16::from(frame.code).into()))
+                }
+                Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_)) => continue,
+                Err(err) => return Received::Failed(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{error::Error, sync::Arc, time::Duration};
+
+    use flate2::{Compress, FlushCompress};
+    use serde_json::json;
+
+    use super::{Compression, Connection, Encoding, Inflater, ZLIB_SUFFIX};
+    use crate::{bot::send_queue::Priority, discord::gateway::RawEvent, testing::MockGateway};
+
+    fn deflate(context: &mut Compress, text: &str) -> Vec<u8> {
+        let mut output = Vec::with_capacity(text.len() + 64);
+        context
+            .compress_vec(text.as_bytes(), &mut output, FlushCompress::Sync)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn zlib_stream() {
+        let mut context = Compress::new(flate2::Compression::default(), true);
+        let mut inflater = Inflater::new();
+
+        let first = deflate(
+            &mut context,
+            r#"{"op":10,"d":{"heartbeat_interval":41250}}"#,
+        );
+        let (head, tail) = first.split_at(first.len() / 2);
+        assert!(inflater.inflate(head).is_none());
+        assert_eq!(
+            inflater.inflate(tail).unwrap().unwrap(),
+            br#"{"op":10,"d":{"heartbeat_interval":41250}}"#
+        );
+
+        let second = deflate(&mut context, r#"{"op":11}"#);
+        assert_eq!(inflater.inflate(&second).unwrap().unwrap(), br#"{"op":11}"#);
+    }
+
+    #[test]
+    fn finished_stream() {
+        let mut context = Compress::new(flate2::Compression::default(), true);
+        let mut inflater = Inflater::new();
+
+        let mut finished = Vec::with_capacity(64);
+        context
+            .compress_vec(br#"{"op":11}"#, &mut finished, FlushCompress::Finish)
+            .unwrap();
+        finished.extend_from_slice(&ZLIB_SUFFIX);
+        assert_eq!(
+            inflater.inflate(&finished).unwrap().unwrap(),
+            br#"{"op":11}"#
+        );
+        assert!(inflater.inflate(&ZLIB_SUFFIX).unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn wait_for_identify() -> Result<(), Box<dyn Error>> {
+        let gateway = MockGateway::start(json!({ "id": "1000" }), 45_000).await?;
+        let connection = Arc::new(Connection::new(Compression::None));
+        connection.change_socket(gateway.url()).await?;
+        let encode = |opcode: u8| Encoding::Json.encode(&RawEvent::new(opcode, json!({})));
+
+        let presence = tokio::spawn({
+            let connection = connection.clone();
+            let presence = encode(3);
+            async move { connection.send(presence, Priority::Normal).await }
+        });
+        let early = tokio::time::timeout(Duration::from_millis(100), gateway.next_payload());
+        assert!(early.await.is_err(), "Should hold presence back");
+
+        connection.send(encode(2), Priority::Identify).await?;
+        presence.await??;
+        assert_eq!(gateway.next_payload().await.unwrap()["op"], 2);
+        assert_eq!(gateway.next_payload().await.unwrap()["op"], 3);
+        Ok(())
     }
 }
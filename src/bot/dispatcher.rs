@@ -0,0 +1,304 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::{Notify, Semaphore};
+
+use super::shutdown::HandlerTracker;
+use crate::prelude::*;
+
+const DEFAULT_QUEUE_SIZE: usize = 128;
+const DEFAULT_MAX_CONCURRENCY: usize = 64;
+// Sequential dispatch is ordered dispatch with one queue for every event.
+const SEQUENTIAL_KEY: &str = "";
+
+type Queues = Arc<Mutex<HashMap<Box<str>, VecDeque<BoxedFuture<()>>>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DispatchKey {
+    Guild,
+    Channel,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum DispatchMode {
+    #[default]
+    Concurrent,
+    Sequential {
+        queue_size: usize,
+    },
+    Ordered {
+        key: DispatchKey,
+        queue_size: usize,
+        max_concurrency: usize,
+    },
+}
+
+impl DispatchMode {
+    #[inline]
+    pub fn sequential() -> Self {
+        Self::Sequential {
+            queue_size: DEFAULT_QUEUE_SIZE,
+        }
+    }
+
+    #[inline]
+    pub fn per_guild() -> Self {
+        Self::Ordered {
+            key: DispatchKey::Guild,
+            queue_size: DEFAULT_QUEUE_SIZE,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+        }
+    }
+
+    #[inline]
+    pub fn per_channel() -> Self {
+        Self::Ordered {
+            key: DispatchKey::Channel,
+            queue_size: DEFAULT_QUEUE_SIZE,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+        }
+    }
+
+    #[inline]
+    pub fn queue_size(mut self, size: usize) -> Self {
+        match &mut self {
+            Self::Concurrent => {}
+            Self::Sequential { queue_size } | Self::Ordered { queue_size, .. } => {
+                *queue_size = size.max(1)
+            }
+        }
+        self
+    }
+
+    #[inline]
+    pub fn max_concurrency(mut self, max: usize) -> Self {
+        if let Self::Ordered {
+            max_concurrency, ..
+        } = &mut self
+        {
+            *max_concurrency = max.max(1);
+        }
+        self
+    }
+}
+
+pub(crate) struct Dispatcher {
+    mode: DispatchMode,
+    handlers: Arc<HandlerTracker>,
+    permits: Option<Arc<Semaphore>>,
+    queues: Queues,
+    space: Arc<Notify>,
+}
+
+impl Dispatcher {
+    pub(crate) fn new(mode: DispatchMode) -> Self {
+        let permits = match mode {
+            DispatchMode::Concurrent => None,
+            DispatchMode::Sequential { .. } => Some(1),
+            DispatchMode::Ordered {
+                max_concurrency, ..
+            } => Some(max_concurrency),
+        };
+        Self {
+            mode,
+            handlers: Default::default(),
+            permits: permits.map(|permits| Arc::new(Semaphore::new(permits))),
+            queues: Default::default(),
+            space: Default::default(),
+        }
+    }
+
+    #[inline]
+    pub(crate) async fn drain(&self) {
+        self.handlers.drain().await
+    }
+
+    // Waits while the queue of the key is full, which holds up the shard's
+    // processor while its reader keeps going.
+    pub(crate) async fn dispatch(
+        &self,
+        guild_id: Option<&ID>,
+        channel_id: Option<&ID>,
+        handler: impl Future<Output = ()> + Send + 'static,
+    ) {
+        let (key, queue_size) = match self.mode {
+            DispatchMode::Concurrent => return self.handlers.spawn(handler),
+            DispatchMode::Sequential { queue_size } => (Some(SEQUENTIAL_KEY), queue_size),
+            DispatchMode::Ordered {
+                key: DispatchKey::Guild,
+                queue_size,
+                ..
+            } => (guild_id.or(channel_id).map(ID::as_str), queue_size),
+            DispatchMode::Ordered {
+                key: DispatchKey::Channel,
+                queue_size,
+                ..
+            } => (channel_id.map(ID::as_str), queue_size),
+        };
+        // Events without a guild or channel are never ordered, they only count
+        // towards the concurrency cap.
+        let Some(key) = key else {
+            let permits = self.permits.clone();
+            return self.handlers.spawn(async move {
+                let _permit = match &permits {
+                    Some(permits) => Some(permits.acquire().await),
+                    None => None,
+                };
+                handler.await;
+            });
+        };
+
+        let mut handler: Option<BoxedFuture<()>> = Some(Box::pin(handler));
+        loop {
+            let space = self.space.notified();
+            tokio::pin!(space);
+            space.as_mut().enable();
+            {
+                let mut queues = self.queues.lock().expect("Should not be poisoned");
+                match queues.get_mut(key) {
+                    Some(queue) if queue.len() >= queue_size => {}
+                    Some(queue) => {
+                        queue.extend(handler.take());
+                        return;
+                    }
+                    None => {
+                        queues.insert(key.into(), VecDeque::new());
+                        drop(queues);
+                        let first = handler.take().expect("Should not be dispatched yet");
+                        return self.run_queue(key.into(), first);
+                    }
+                }
+            }
+            space.await;
+        }
+    }
+
+    // A queue exists for as long as its runner, so handlers of the same key
+    // never overlap.
+    fn run_queue(&self, key: Box<str>, first: BoxedFuture<()>) {
+        let queues = self.queues.clone();
+        let permits = self.permits.clone();
+        let space = self.space.clone();
+        self.handlers.spawn(async move {
+            let mut next = Some(first);
+            while let Some(handler) = next {
+                {
+                    let _permit = match &permits {
+                        Some(permits) => Some(permits.acquire().await),
+                        None => None,
+                    };
+                    handler.await;
+                }
+                let mut queues = queues.lock().expect("Should not be poisoned");
+                let queue = queues.get_mut(&key).expect("Should exist while running");
+                next = queue.pop_front();
+                if next.is_none() {
+                    queues.remove(&key);
+                }
+                drop(queues);
+                space.notify_waiters();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        convert::Infallible,
+        error::Error,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use serde_json::{json, Value};
+    use tokio::sync::mpsc::{self, UnboundedSender};
+
+    use super::{DispatchMode, Dispatcher};
+    use crate::{bot::Encoding, prelude::*, testing::MockDiscord};
+
+    async fn record(
+        dispatcher: &Dispatcher,
+        channel_id: &str,
+        order: &Arc<Mutex<Vec<u32>>>,
+        value: u32,
+    ) {
+        let order = order.clone();
+        dispatcher
+            .dispatch(None, Some(ID::from_raw(channel_id)), async move {
+                tokio::time::sleep(Duration::from_millis(10 * (5 - value as u64))).await;
+                order.lock().unwrap().push(value);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn ordered_per_channel() {
+        let dispatcher = Dispatcher::new(DispatchMode::per_channel().queue_size(2));
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for value in 0..5 {
+            record(&dispatcher, "1", &order, value).await;
+        }
+        dispatcher.drain().await;
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn concurrent_across_channels() {
+        let dispatcher = Dispatcher::new(DispatchMode::per_channel());
+        let order = Arc::new(Mutex::new(Vec::new()));
+        record(&dispatcher, "1", &order, 0).await;
+        record(&dispatcher, "2", &order, 4).await;
+        dispatcher.drain().await;
+        assert_eq!(*order.lock().unwrap(), vec![4, 0]);
+    }
+
+    struct Typing(UnboundedSender<u64>);
+
+    impl BotImpl for Typing {
+        type Error = Infallible;
+
+        async fn on_unknown_event(
+            bot: Bot<Self>,
+            _: Box<str>,
+            data: Value,
+        ) -> Result<(), Infallible> {
+            let value = data["value"].as_u64().expect("Should be sent");
+            tokio::time::sleep(Duration::from_millis(10 * (5 - value))).await;
+            let _ = bot.implementation().0.send(value);
+            Ok(())
+        }
+    }
+
+    // ETF sends snowflakes as integers, they still have to key the dispatch.
+    #[tokio::test]
+    async fn ordered_over_etf() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        let (sender, mut seen) = mpsc::unbounded_channel();
+        let bot = mock
+            .template()
+            .intents(Intents::GUILDS)
+            .encoding(Encoding::Etf)
+            .dispatch_mode(DispatchMode::per_channel())
+            .implement(Typing(sender), mock.token());
+        let order = mock
+            .run_until(bot, async {
+                for value in 0..5u64 {
+                    mock.gateway.dispatch(
+                        "TYPING_START",
+                        json!({ "channel_id": 1196020343484190790u64, "value": value }),
+                    );
+                }
+                let mut order = Vec::new();
+                while order.len() < 5 {
+                    order.push(seen.recv().await.expect("Should be running"));
+                }
+                order
+            })
+            .await;
+        assert_eq!(order, vec![0, 1, 2, 3, 4]);
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    error::Error,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{FutureExt, Stream};
+use tokio::{sync::mpsc::Receiver, task::JoinHandle};
+
+use super::ShardId;
+use crate::discord::gateway::DispatchedEvent;
+
+type ShardsResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+// Events waiting to be taken from the stream before shards stop processing.
+pub(crate) const EVENT_BUFFER: usize = 256;
+
+pub struct EventStream {
+    events: Receiver<(ShardId, DispatchedEvent)>,
+    shards: Option<JoinHandle<ShardsResult>>,
+    error: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl EventStream {
+    pub(crate) fn new(
+        events: Receiver<(ShardId, DispatchedEvent)>,
+        shards: JoinHandle<ShardsResult>,
+    ) -> Self {
+        Self {
+            events,
+            shards: Some(shards),
+            error: None,
+        }
+    }
+
+    // The stream ends once every shard has stopped. If they stopped because
+    // of an error, it can be taken from here afterwards.
+    #[inline]
+    pub fn take_error(&mut self) -> Option<Box<dyn Error + Send + Sync>> {
+        self.error.take()
+    }
+}
+
+impl Stream for EventStream {
+    type Item = (ShardId, DispatchedEvent);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Poll::Ready(Some(event)) = self.events.poll_recv(cx) {
+            return Poll::Ready(Some(event));
+        }
+        let Some(shards) = self.shards.as_mut() else {
+            return Poll::Ready(None);
+        };
+        let Poll::Ready(finished) = shards.poll_unpin(cx) else {
+            return Poll::Pending;
+        };
+        self.shards = None;
+        self.error = match finished {
+            Ok(res) => res.err(),
+            Err(err) => Some(err.into()),
+        };
+        // Events sent right before the shards stopped are still delivered.
+        match self.events.try_recv() {
+            Ok(event) => Poll::Ready(Some(event)),
+            Err(_) => Poll::Ready(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::error::Error;
+
+    use futures::StreamExt;
+    use serde_json::json;
+
+    use crate::{
+        bot::ShardId, discord::gateway::DispatchedEvent, prelude::*, testing::MockDiscord,
+    };
+
+    #[tokio::test]
+    async fn event_stream() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        let (bot, mut events) = mock
+            .template()
+            .intents(Intents::GUILDS)
+            .connect(mock.token())
+            .await?;
+        assert_eq!(bot.shard(), ShardId::new(0, 1));
+
+        let (shard, ready) = events.next().await.expect("Should receive ready");
+        assert_eq!(shard, bot.shard());
+        assert_eq!(ready.name(), "READY");
+
+        mock.gateway
+            .dispatch("TYPING_START", json!({ "channel_id": "2000" }));
+        let (_, typing) = events.next().await.expect("Should receive typing");
+        match typing {
+            DispatchedEvent::Unknown { event_name, data } => {
+                assert_eq!(event_name.as_ref(), "TYPING_START");
+                assert_eq!(data["channel_id"], "2000");
+            }
+            event => panic!("Unexpected event: {:?}", event),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn shut_down_once_dropped() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        let (_, mut events) = mock
+            .template()
+            .intents(Intents::GUILDS)
+            .connect(mock.token())
+            .await?;
+        events.next().await.expect("Should receive ready");
+        drop(events);
+
+        mock.gateway
+            .dispatch("TYPING_START", json!({ "channel_id": "2000" }));
+        assert_eq!(mock.gateway.wait_for_close().await, Some(Some(1000)));
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use std::{any::Any, error::Error, fmt::Display};
+
+use super::ShardId;
+
+#[derive(Debug)]
+pub enum HandlerError {
+    Panicked(Box<str>),
+    Failed(Box<dyn Error + Send + Sync>),
+}
+
+impl HandlerError {
+    pub(crate) fn from_panic(panic: Box<dyn Any + Send>) -> Self {
+        let message = match panic.downcast::<String>() {
+            Ok(message) => message.as_str().into(),
+            Err(panic) => match panic.downcast::<&'static str>() {
+                Ok(message) => (*message).into(),
+                Err(_) => "Box<dyn Any>".into(),
+            },
+        };
+        Self::Panicked(message)
+    }
+}
+
+impl Display for HandlerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Panicked(message) => write!(f, "Handler panicked: {}", message),
+            Self::Failed(err) => write!(f, "Handler failed: {}", err),
+        }
+    }
+}
+
+impl Error for HandlerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Failed(err) => Some(err.as_ref()),
+            Self::Panicked(_) => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ErrorContext {
+    pub(crate) event: Box<str>,
+    pub(crate) shard: ShardId,
+    pub(crate) error: HandlerError,
+}
+
+impl ErrorContext {
+    #[inline]
+    pub fn event(&self) -> &str {
+        &self.event
+    }
+
+    #[inline]
+    pub fn shard(&self) -> ShardId {
+        self.shard
+    }
+
+    #[inline]
+    pub fn error(&self) -> &HandlerError {
+        &self.error
+    }
+
+    #[inline]
+    pub fn into_error(self) -> HandlerError {
+        self.error
+    }
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} handler on shard {} failed: {}",
+            self.event, self.shard, self.error
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::error::Error;
+
+    use tokio::sync::mpsc::{self, UnboundedSender};
+
+    use super::{ErrorContext, HandlerError};
+    use crate::{
+        bot::ShardId,
+        discord::{gateway::MessageCreatedEvent, RestError},
+        prelude::*,
+        testing::MockDiscord,
+    };
+
+    struct FailingBot(UnboundedSender<ErrorContext>);
+
+    impl BotImpl for FailingBot {
+        type Error = RestError;
+
+        async fn on_message_created(
+            _: Bot<Self>,
+            msg: MessageCreatedEvent,
+        ) -> Result<(), RestError> {
+            if msg.message.content() == Some("panic") {
+                panic!("Handler gave up");
+            }
+            msg.message.channel().await?;
+            Ok(())
+        }
+
+        async fn on_error(bot: Bot<Self>, context: ErrorContext) {
+            let _ = bot.implementation().0.send(context);
+        }
+    }
+
+    #[tokio::test]
+    async fn handler_errors() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        let (sender, mut errors) = mpsc::unbounded_channel();
+        let bot = mock
+            .template()
+            .intents(Intents::GUILDS | Intents::GUILD_MESSAGES | Intents::MESSAGE_CONTENT)
+            .implement(FailingBot(sender), mock.token());
+        let mut contexts = mock
+            .run_until(bot, async {
+                mock.message_create("4000", "2000", "panic");
+                mock.message_create("4001", "2000", "fail");
+                vec![errors.recv().await.unwrap(), errors.recv().await.unwrap()]
+            })
+            .await;
+        contexts.sort_by_key(|context| matches!(context.error(), HandlerError::Failed(_)));
+
+        for context in &contexts {
+            assert_eq!(context.event(), "MESSAGE_CREATE");
+            assert_eq!(context.shard(), ShardId::new(0, 1));
+        }
+        assert!(matches!(
+            contexts[0].error(),
+            HandlerError::Panicked(message) if message.as_ref() == "Handler gave up"
+        ));
+        assert!(matches!(
+            contexts[1].error(),
+            HandlerError::Failed(err) if err.to_string().contains("404")
+        ));
+        Ok(())
+    }
+}
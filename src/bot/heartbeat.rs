@@ -0,0 +1,106 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use futures::Future;
+use rand::Rng;
+
+#[derive(Debug, Default)]
+struct HeartbeatState {
+    last_sent: Option<Instant>,
+    awaiting_ack: bool,
+    latency: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct HeartbeatTracker(Mutex<HeartbeatState>);
+
+impl HeartbeatTracker {
+    pub(crate) fn reset(&self) {
+        let mut state = self.0.lock().expect("Should not be poisoned");
+        state.last_sent = None;
+        state.awaiting_ack = false;
+    }
+
+    // Returns `false` if the previous heartbeat was never acknowledged,
+    // which means the connection has become a zombie.
+    pub(crate) fn beat(&self) -> bool {
+        let mut state = self.0.lock().expect("Should not be poisoned");
+        if state.awaiting_ack {
+            return false;
+        }
+        state.last_sent = Some(Instant::now());
+        state.awaiting_ack = true;
+        true
+    }
+
+    // Heartbeats requested by the gateway do not count towards zombie
+    // detection, since they are sent outside the regular interval.
+    pub(crate) fn beat_requested(&self) {
+        self.0.lock().expect("Should not be poisoned").last_sent = Some(Instant::now());
+    }
+
+    pub(crate) fn ack(&self) {
+        let mut state = self.0.lock().expect("Should not be poisoned");
+        state.awaiting_ack = false;
+        if let Some(sent) = state.last_sent {
+            state.latency = Some(sent.elapsed());
+        }
+    }
+
+    pub(crate) fn latency(&self) -> Option<Duration> {
+        self.0.lock().expect("Should not be poisoned").latency
+    }
+}
+
+// Beats every interval until `beat` returns `false`. The first beat lands at
+// a random point of the first interval, so shards started together do not
+// beat in lockstep.
+pub(crate) async fn run<F, Fut>(interval: Duration, mut beat: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let jitter = rand::thread_rng().gen_range(0.0..1.0);
+    tokio::time::sleep(interval.mul_f64(jitter)).await;
+    let mut heartbeat = tokio::time::interval(interval);
+    loop {
+        heartbeat.tick().await;
+        if !beat().await {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use futures::future;
+    use tokio::time::Instant;
+
+    const INTERVAL: Duration = Duration::from_millis(41_250);
+
+    #[tokio::test(start_paused = true)]
+    async fn first_heartbeat_jitter() {
+        let mut first_beats = Vec::new();
+        for _ in 0..3 {
+            let started = Instant::now();
+            let mut beats = Vec::new();
+            super::run(INTERVAL, || {
+                beats.push(started.elapsed());
+                future::ready(beats.len() < 3)
+            })
+            .await;
+
+            // An unjittered heartbeater would only beat after a whole interval.
+            assert!(beats[0] < INTERVAL, "First heartbeat took {:?}", beats[0]);
+            assert_eq!(beats[1] - beats[0], INTERVAL);
+            assert_eq!(beats[2] - beats[1], INTERVAL);
+            first_beats.push(beats[0]);
+        }
+        first_beats.dedup();
+        assert!(first_beats.len() > 1, "First heartbeats were not jittered");
+    }
+}
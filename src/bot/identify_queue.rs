@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use tokio::{
+    sync::{Mutex, MutexGuard},
+    time::{sleep_until, Instant},
+};
+
+use super::ShardId;
+use crate::discord::gateway::SessionStartLimit;
+
+const IDENTIFY_INTERVAL: Duration = Duration::from_secs(5);
+const SESSION_RESET_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug)]
+struct SessionBudget {
+    total: u32,
+    remaining: u32,
+    reset_at: Instant,
+}
+
+#[derive(Debug)]
+pub(crate) struct IdentifyQueue {
+    budget: Mutex<SessionBudget>,
+    buckets: Box<[Mutex<Option<Instant>>]>,
+}
+
+impl IdentifyQueue {
+    pub(crate) fn new(limit: SessionStartLimit) -> Self {
+        let buckets = (0..limit.max_concurrency.max(1))
+            .map(|_| Mutex::new(None))
+            .collect();
+        Self {
+            budget: Mutex::new(SessionBudget {
+                total: limit.total,
+                remaining: limit.remaining,
+                reset_at: Instant::now() + Duration::from_millis(limit.reset_after),
+            }),
+            buckets,
+        }
+    }
+
+    // Shards sharing a rate limit bucket identify one at a time, at most once
+    // every five seconds, and nobody identifies once the daily budget is spent.
+    // The bucket stays taken until the returned permit is dropped.
+    pub(crate) async fn wait(&self, shard: ShardId) -> IdentifyPermit<'_> {
+        let bucket = &self.buckets[shard.id() as usize % self.buckets.len()];
+        let last_identify = bucket.lock().await;
+        if let Some(last_identify) = *last_identify {
+            sleep_until(last_identify + IDENTIFY_INTERVAL).await;
+        }
+        self.take_session().await;
+        IdentifyPermit(last_identify)
+    }
+
+    async fn take_session(&self) {
+        let mut budget = self.budget.lock().await;
+        if budget.reset_at <= Instant::now() {
+            budget.remaining = budget.total;
+            budget.reset_at = Instant::now() + SESSION_RESET_INTERVAL;
+        }
+        if budget.remaining == 0 {
+            sleep_until(budget.reset_at).await;
+            budget.remaining = budget.total;
+            budget.reset_at = Instant::now() + SESSION_RESET_INTERVAL;
+        }
+        budget.remaining = budget.remaining.saturating_sub(1);
+    }
+}
+
+// Dropped right after identifying, so the next shard in the bucket waits five
+// seconds from the identify itself rather than from when the slot was taken.
+pub(crate) struct IdentifyPermit<'a>(MutexGuard<'a, Option<Instant>>);
+
+impl Drop for IdentifyPermit<'_> {
+    fn drop(&mut self) {
+        *self.0 = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::{
+        bot::ShardConfig,
+        testing::{step_paused_time, MockDiscord},
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn shards_wait_before_connecting() {
+        const HEARTBEAT_INTERVAL: u64 = 2_500;
+        let mock = MockDiscord::with_heartbeat_interval(HEARTBEAT_INTERVAL)
+            .await
+            .unwrap();
+        tokio::spawn(step_paused_time(Duration::from_millis(25)));
+        let (_bot, _events) = mock
+            .template()
+            .shards(ShardConfig::Fixed(3))
+            .connect(mock.token())
+            .await
+            .unwrap();
+
+        let mut identified = Vec::new();
+        while identified.len() < 3 {
+            mock.gateway.wait_for_op(2).await.unwrap();
+            identified.push(Instant::now());
+        }
+        // Identifies are only seen once they arrive, which may be a few steps
+        // after they were sent.
+        for pair in identified.windows(2) {
+            assert!(pair[1] - pair[0] >= Duration::from_millis(4_500));
+        }
+
+        // Shards still waiting for their turn must not have a socket whose
+        // heartbeats go unread, or they would turn into zombies and reconnect.
+        tokio::time::sleep(Duration::from_millis(HEARTBEAT_INTERVAL * 3)).await;
+        assert_eq!(mock.gateway.connections(), 3);
+        assert_eq!(mock.gateway.sessions(), 3);
+    }
+}
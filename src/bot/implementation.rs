@@ -1,16 +1,67 @@
+use std::{convert::Infallible, error::Error};
+
 use futures::Future;
+use serde_json::Value;
 
-use crate::discord::gateway::MessageCreatedEvent;
+use crate::discord::gateway::{GuildMembersChunkEvent, MessageCreatedEvent, RawEvent};
 
-use super::{Bot, CommandRegister};
+use super::{Bot, CommandRegister, ErrorContext};
 
 pub trait BotImpl: 'static + Sized {
-    fn on_ready(_: Bot<Self>) -> impl Future<Output = ()> + Send {
-        async { println!("[MILI] The bot is ready!") }
+    // Returned by every handler below. There is no default, so since 0.3 every
+    // implementation names it, `Infallible` when none of its handlers fail.
+    type Error: Into<Box<dyn Error + Send + Sync>> + Send;
+
+    fn on_ready(_: Bot<Self>) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async {
+            tracing::info!("The bot is ready");
+            Ok(())
+        }
+    }
+
+    fn on_message_created(
+        _: Bot<Self>,
+        _: MessageCreatedEvent,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+
+    // Only chunks nobody asked for through `Bot::request_guild_members` end
+    // up here, requested ones are handed back to the request instead.
+    fn on_guild_members_chunk(
+        _: Bot<Self>,
+        _: GuildMembersChunkEvent,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
     }
 
-    fn on_message_created(_: Bot<Self>, _: MessageCreatedEvent) -> impl Future<Output = ()> + Send {
-        async {}
+    // Awaited on the shard's processor before the payload is handled, so it
+    // sees every payload in the order it was received.
+    fn on_raw_event(
+        _: Bot<Self>,
+        _: &RawEvent,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+
+    fn on_unknown_event(
+        _: Bot<Self>,
+        _: Box<str>,
+        _: Value,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+
+    // Called with every panic or error coming out of the handlers above.
+    fn on_error(_: Bot<Self>, context: ErrorContext) -> impl Future<Output = ()> + Send {
+        async move {
+            tracing::error!(
+                event = context.event(),
+                shard = %context.shard(),
+                error = %context.error(),
+                "Handler failed"
+            )
+        }
     }
 
     fn command_register(_: &mut CommandRegister) {}
@@ -19,4 +70,66 @@ pub trait BotImpl: 'static + Sized {
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Ord, Eq, Hash)]
 pub struct BlanketImpl;
 
-impl BotImpl for BlanketImpl {}
+impl BotImpl for BlanketImpl {
+    type Error = Infallible;
+}
+
+#[cfg(test)]
+mod test {
+    use std::{convert::Infallible, error::Error};
+
+    use serde_json::{json, Value};
+    use tokio::sync::mpsc::{self, UnboundedSender};
+
+    use crate::{discord::gateway::RawEvent, prelude::*, testing::MockDiscord};
+
+    struct Recorder(UnboundedSender<String>);
+
+    impl BotImpl for Recorder {
+        type Error = Infallible;
+
+        async fn on_raw_event(bot: Bot<Self>, raw: &RawEvent) -> Result<(), Infallible> {
+            let name = raw.event_name().unwrap_or("-");
+            let _ = bot
+                .implementation()
+                .0
+                .send(format!("raw {} {}", raw.opcode(), name));
+            Ok(())
+        }
+
+        async fn on_unknown_event(
+            bot: Bot<Self>,
+            name: Box<str>,
+            data: Value,
+        ) -> Result<(), Infallible> {
+            let _ = bot
+                .implementation()
+                .0
+                .send(format!("unknown {} {}", name, data));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn raw_and_unknown_events() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        let (sender, mut seen) = mpsc::unbounded_channel();
+        let bot = mock
+            .template()
+            .intents(Intents::GUILDS)
+            .implement(Recorder(sender), mock.token());
+        mock.run_until(bot, async {
+            assert_eq!(seen.recv().await.unwrap(), "raw 10 -");
+            assert_eq!(seen.recv().await.unwrap(), "raw 0 READY");
+            mock.gateway
+                .dispatch("TYPING_START", json!({ "channel_id": "2000" }));
+            assert_eq!(seen.recv().await.unwrap(), "raw 0 TYPING_START");
+            assert_eq!(
+                seen.recv().await.unwrap(),
+                r#"unknown TYPING_START {"channel_id":"2000"}"#
+            );
+        })
+        .await;
+        Ok(())
+    }
+}
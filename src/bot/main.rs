@@ -1,34 +1,91 @@
-use std::{error::Error, sync::Arc};
+use std::{
+    collections::HashMap,
+    error::Error,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+    time::{Duration, Instant},
+};
 
-use super::{client::DiscordClient, connection::Connection};
+use super::{
+    cache::Cache,
+    client::DiscordClient,
+    connection::{socket_url, Compression, Encoding, Received},
+    dispatcher::Dispatcher,
+    handler::{ErrorContext, HandlerError},
+    heartbeat,
+    identify_queue::IdentifyQueue,
+    members::{MemberCollector, MemberQuery, RequestMembersError},
+    middleware::MiddlewareChain,
+    send_queue::Priority,
+    shard::{Shard, ShardId},
+    shutdown::ShutdownHandle,
+    ReconnectConfig,
+};
 use crate::{
     discord::{
         gateway::{
-            recover_data::RecoverData, ConnectionProperties, DispatchedEvent, Event, IdentifyData,
-            RawEvent, ReadyEvent,
+            recover_data::RecoverData, ConnectionProperties, DispatchedEvent, Event, GatewayError,
+            IdentifyData, RawEvent, RequestGuildMembersData, ResumeData,
         },
-        User,
+        Member, Presence, RawUser, User,
     },
+    metrics,
     prelude::*,
 };
-use serde_json::{from_str, to_string, to_value};
-use tokio::{sync::Mutex, task::JoinHandle};
+use futures::future;
+use rand::Rng;
+use serde_json::{to_value, Value};
+use tokio::{
+    sync::mpsc::{Receiver, Sender},
+    task::JoinHandle,
+};
+use tokio_tungstenite::tungstenite::Error as WsError;
+use tracing::{debug, debug_span, error, info, trace, warn, Instrument};
 
 pub(crate) struct RawBot<Impl> {
     pub(super) token: Box<str>,
     pub(super) state: Impl,
-    pub(super) bot: User,
     pub(super) intents: Intents,
     pub(super) client: DiscordClient,
-    pub(super) connection: Connection,
-    pub(super) last_sequence_number: Mutex<Option<usize>>,
+    pub(super) me: RawUser,
+    pub(super) gateway_url: Box<str>,
+    pub(super) gateway_url_forced: bool,
+    pub(super) reconnect: ReconnectConfig,
+    pub(super) encoding: Encoding,
+    pub(super) compression: Compression,
+    pub(super) identify_queue: IdentifyQueue,
+    pub(super) presence: StdMutex<Option<Presence>>,
+    pub(super) running_shards: StdMutex<HashMap<u32, Arc<Shard>>>,
+    pub(super) members: MemberCollector,
+    pub(super) events: Option<Sender<(ShardId, DispatchedEvent)>>,
+    pub(super) shutdown: ShutdownHandle,
+    pub(super) shutdown_timeout: Duration,
+    pub(super) dispatcher: Dispatcher,
+    pub(super) middleware: MiddlewareChain,
+    // Set until the missing MESSAGE_CONTENT intent has been warned about.
+    pub(super) content_missing: AtomicBool,
+}
+
+impl<Impl> RawBot<Impl> {
+    #[inline]
+    pub(super) fn socket_url(&self, base: &str) -> String {
+        socket_url(
+            base,
+            self.client.api_version(),
+            self.encoding,
+            self.compression,
+        )
+    }
 }
 
-pub struct Bot<Impl>(Arc<RawBot<Impl>>);
+pub struct Bot<Impl>(Arc<RawBot<Impl>>, Arc<Shard>);
 
 impl<Impl> Clone for Bot<Impl> {
     fn clone(&self) -> Self {
-        Self(self.0.clone())
+        Self(self.0.clone(), self.1.clone())
     }
 }
 
@@ -36,8 +93,8 @@ impl<Impl> Bot<Impl>
 where
     Impl: BotImpl,
 {
-    pub(crate) fn from_raw(bot: RawBot<Impl>) -> Self {
-        Self(Arc::new(bot))
+    pub(crate) fn from_raw(bot: Arc<RawBot<Impl>>, shard: Arc<Shard>) -> Self {
+        Self(bot, shard)
     }
 
     pub fn token(&self) -> &str {
@@ -63,19 +120,65 @@ where
         &self.0.client
     }
 
+    // Fetched before any shard connects and kept up to date by READY and
+    // USER_UPDATE afterwards.
+    pub fn me(&self) -> User {
+        self.cache()
+            .me()
+            .unwrap_or_else(|| User::from_raw(self.0.me.clone(), self.client().clone()))
+    }
+
+    #[inline]
+    pub fn cache(&self) -> Cache<'_> {
+        self.client().cache()
+    }
+
+    #[inline]
+    pub fn shard(&self) -> ShardId {
+        self.1.id
+    }
+
+    #[inline]
+    pub fn latency(&self) -> Option<Duration> {
+        self.1.heartbeat.latency()
+    }
+
+    pub fn presence(&self) -> Option<Presence> {
+        self.0
+            .presence
+            .lock()
+            .expect("Should not be poisoned")
+            .clone()
+    }
+
+    #[inline]
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.0.shutdown.clone()
+    }
+
     #[inline]
-    pub fn me(&self) -> &User {
-        &self.0.bot
+    pub fn shutdown(&self) {
+        self.0.shutdown.shutdown()
+    }
+
+    fn warn_missing_content(&self) {
+        if !self.0.content_missing.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        warn!(
+            "Handling messages without the MESSAGE_CONTENT intent, \
+             Message::content() will be None for most messages"
+        );
     }
 
     #[inline]
     async fn update_sequence_number(&self, sequence_number: usize) {
-        *self.0.last_sequence_number.lock().await = Some(sequence_number);
+        *self.1.last_sequence_number.lock().await = Some(sequence_number);
     }
 
     #[inline]
     async fn get_seqenuce_number(&self) -> Option<usize> {
-        *self.0.last_sequence_number.lock().await
+        *self.1.last_sequence_number.lock().await
     }
 
     fn identify_data(&self) -> RawEvent {
@@ -89,6 +192,8 @@ where
             token: self.token(),
             intents: self.intents().as_u64(),
             properties: prop,
+            shard: self.shard().as_array(),
+            presence: self.presence(),
         };
         let val = to_value(identify_data).expect("Should succeed");
         RawEvent::new(2, val)
@@ -99,147 +204,754 @@ impl<Impl> Bot<Impl>
 where
     Impl: BotImpl + Send + Sync,
 {
-    async fn ready(&self) -> Result<RecoverData, Box<dyn Error>> {
+    async fn reidentify(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        *self.1.last_sequence_number.lock().await = None;
+        self.identify().await
+    }
+
+    async fn identify(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
         let data = self.identify_data();
-        let identify_data = serde_json::to_string(&data).expect("should succeed");
-        self.0
+        info!(intents = ?self.intents(), "Identifying");
+        self.send_event_with(&data, Priority::Identify).await?;
+        Ok(())
+    }
+
+    async fn hello(&self) -> Result<JoinHandle<()>, Box<dyn Error + Send + Sync>> {
+        let hello_event = {
+            let event = match self.1.connection.recv().await {
+                Received::Payload(payload) => payload,
+                Received::Closed(Some(code)) if code.is_fatal() => {
+                    return Err(GatewayError::Closed(code).into())
+                }
+                Received::Closed(_) => return Err(WsError::ConnectionClosed.into()),
+                Received::Failed(err) => return Err(err.into()),
+            };
+            let raw = self.decode_event(&event)?;
+            if raw.opcode() != 10 {
+                return Err(GatewayError::UnexpectedEvent(raw).into());
+            }
+            let hello = raw.clone().try_into_mature(self.client().clone())?;
+            self.1.backlog.push(raw).await;
+            hello
+        };
+        if let Event::Hello { heartbeat_interval } = hello_event {
+            debug!(?heartbeat_interval, "Received hello");
+            let bot = self.clone();
+            self.1.heartbeat.reset();
+            let heartbeater = tokio::spawn(
+                heartbeat::run(heartbeat_interval, move || {
+                    let bot = bot.clone();
+                    async move {
+                        if !bot.1.heartbeat.beat() {
+                            warn!("Heartbeat was not acknowledged, connection is a zombie");
+                            return false;
+                        }
+                        if let Err(err) = bot.send_heartbeat().await {
+                            warn!(error = %err, "Failed to send heartbeat");
+                            return false;
+                        }
+                        true
+                    }
+                })
+                .in_current_span(),
+            );
+            Ok(heartbeater)
+        } else {
+            unreachable!("Opcode 10 should always be matured as hello event")
+        }
+    }
+
+    // The span is created right away, so spawned handlers still belong to
+    // the shard that received their event.
+    fn supervise<E>(
+        self,
+        event: Box<str>,
+        handler: impl Future<Output = Result<(), E>>,
+    ) -> impl Future<Output = ()>
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let span = debug_span!("handler", event = %event);
+        async move {
+            debug!("Handler started");
+            let started = Instant::now();
+            let error = match AssertUnwindSafe(handler).catch_unwind().await {
+                Ok(res) => match res {
+                    Ok(()) => {
+                        debug!("Handler finished");
+                        metrics::handler_finished(&event, started.elapsed(), None);
+                        return;
+                    }
+                    Err(err) => HandlerError::Failed(err.into()),
+                },
+                Err(panic) => HandlerError::from_panic(panic),
+            };
+            debug!(error = %error, "Handler failed");
+            let kind = match error {
+                HandlerError::Panicked(_) => "panic",
+                HandlerError::Failed(_) => "error",
+            };
+            metrics::handler_finished(&event, started.elapsed(), Some(kind));
+            let context = ErrorContext {
+                event,
+                shard: self.shard(),
+                error,
+            };
+            // There is nowhere left to report a panicking error hook to.
+            let _ = AssertUnwindSafe(Impl::on_error(self, context))
+                .catch_unwind()
+                .await;
+        }
+        .instrument(span)
+    }
+
+    async fn send_heartbeat(&self) -> Result<(), WsError> {
+        let seq_num = self.get_seqenuce_number().await;
+        let data = to_value(seq_num).expect("Should be valid");
+        trace!(sequence_number = ?seq_num, "Sending heartbeat");
+        let heartbeat_event = RawEvent::new(1, data);
+        self.send_event_with(&heartbeat_event, Priority::Heartbeat)
+            .await
+    }
+
+    // Updates every running shard, shards that identify later pick the
+    // presence up from there.
+    pub async fn set_presence(&self, presence: Presence) -> Result<(), WsError> {
+        let event = RawEvent::new(3, to_value(&presence).expect("Should succeed"));
+        *self.0.presence.lock().expect("Should not be poisoned") = Some(presence);
+        let shards: Vec<_> = self
+            .0
+            .running_shards
+            .lock()
+            .expect("Should not be poisoned")
+            .values()
+            .cloned()
+            .collect();
+        let event = &event;
+        let sends = shards
+            .into_iter()
+            .map(|shard| Self(self.0.clone(), shard))
+            .map(|bot| async move { bot.send_event(event).await });
+        future::join_all(sends).await.into_iter().collect()
+    }
+
+    pub async fn request_guild_members(
+        &self,
+        guild_id: &ID,
+        query: MemberQuery,
+        presences: bool,
+    ) -> Result<Vec<Member>, RequestMembersError> {
+        const TIMEOUT: Duration = Duration::from_secs(60);
+
+        if !self.intents().contains(Intents::GUILD_MEMBERS) {
+            return Err(RequestMembersError::MissingIntent(Intents::GUILD_MEMBERS));
+        }
+        if presences && !self.intents().contains(Intents::GUILD_PRESENCES) {
+            return Err(RequestMembersError::MissingIntent(Intents::GUILD_PRESENCES));
+        }
+        let Ok(raw_guild_id) = guild_id.parse::<u64>() else {
+            return Err(RequestMembersError::InvalidGuildId(guild_id.to_owned()));
+        };
+        let shard = ShardId::for_guild(raw_guild_id, self.shard().total());
+        let Some(shard) = self
+            .0
+            .running_shards
+            .lock()
+            .expect("Should not be poisoned")
+            .get(&shard.id())
+            .cloned()
+        else {
+            return Err(RequestMembersError::ShardNotRunning(guild_id.to_owned()));
+        };
+        let bot = Self(self.0.clone(), shard);
+
+        let nonce: Box<str> = format!("{:032x}", rand::random::<u128>()).into();
+        let (query_str, limit, user_ids) = match &query {
+            MemberQuery::Query { query, limit } => (Some(query.as_ref()), *limit, None),
+            MemberQuery::UserIds(user_ids) => (None, 0, Some(user_ids.as_slice())),
+        };
+        let data = RequestGuildMembersData {
+            guild_id: guild_id.as_str(),
+            query: query_str,
+            limit,
+            presences,
+            user_ids,
+            nonce: &nonce,
+        };
+        let event = RawEvent::new(8, to_value(data).expect("Should succeed"));
+
+        let members = self.0.members.register(nonce.clone());
+        if let Err(err) = bot.send_event(&event).await {
+            self.0.members.cancel(&nonce);
+            return Err(err.into());
+        }
+        match tokio::time::timeout(TIMEOUT, members).await {
+            Ok(Ok(members)) => Ok(members),
+            Ok(Err(_)) => Err(RequestMembersError::ShardClosed),
+            Err(_) => {
+                self.0.members.cancel(&nonce);
+                Err(RequestMembersError::Timeout)
+            }
+        }
+    }
+
+    #[inline]
+    async fn send_event(&self, event: &RawEvent) -> Result<(), WsError> {
+        self.send_event_with(event, Priority::Normal).await
+    }
+
+    async fn send_event_with(&self, event: &RawEvent, priority: Priority) -> Result<(), WsError> {
+        self.1
             .connection
-            .send(identify_data)
+            .send(self.0.encoding.encode(event), priority)
             .await
-            .expect("Should succeed");
+    }
 
-        let ready: Event = from_str::<RawEvent>(
-            &self
-                .0
+    fn decode_event(&self, payload: &[u8]) -> Result<RawEvent, Box<dyn Error + Send + Sync>> {
+        self.0.encoding.decode(payload)
+    }
+
+    async fn resume_data(&self, recover_data: &RecoverData) -> RawEvent {
+        let resume_data = ResumeData {
+            token: self.token(),
+            session_id: &recover_data.session_id,
+            seq: self.get_seqenuce_number().await,
+        };
+        let val = to_value(resume_data).expect("Should succeed");
+        RawEvent::new(6, val)
+    }
+
+    // New sessions take their identify slot before the socket is opened, so
+    // nothing keeps the shard from reading heartbeat ACKs once hello arrived.
+    async fn connect(
+        &self,
+        recover_data: Option<&RecoverData>,
+    ) -> Result<JoinHandle<()>, Box<dyn Error + Send + Sync>> {
+        if let Some(recover_data) = recover_data {
+            info!(url = %recover_data.resume_url, "Resuming session");
+            self.1
                 .connection
-                .recv()
-                .await
-                .expect("Should not have closed")?,
-        )?
-        .try_into_mature(self.client().clone())
-        .expect("Should be valid event");
-        if let Event::Dispatch {
-            sequence_number,
-            event,
-        } = ready
-        {
-            if let DispatchedEvent::Ready(ReadyEvent {
-                session_id,
-                resume_gateway_url,
-            }) = event
-            {
-                tokio::spawn(Impl::on_ready(self.clone()));
-                self.update_sequence_number(sequence_number).await;
-                Ok(RecoverData {
-                    session_id,
-                    resume_url: resume_gateway_url,
-                })
-            } else {
-                unimplemented!()
+                .change_socket(&self.0.socket_url(&recover_data.resume_url))
+                .await?;
+            let heartbeater = self.hello().await?;
+            let resume_data = self.resume_data(recover_data).await;
+            let sended = self.send_event_with(&resume_data, Priority::Identify).await;
+            if let Err(err) = sended {
+                heartbeater.abort();
+                return Err(err.into());
             }
+            Ok(heartbeater)
         } else {
-            unimplemented!()
+            let _permit = self.0.identify_queue.wait(self.shard()).await;
+            info!(url = %self.0.gateway_url, "Connecting with a new session");
+            self.1
+                .connection
+                .change_socket(&self.0.socket_url(&self.0.gateway_url))
+                .await?;
+            let heartbeater = self.hello().await?;
+            if let Err(err) = self.reidentify().await {
+                heartbeater.abort();
+                return Err(err);
+            }
+            Ok(heartbeater)
         }
     }
 
-    async fn hello(&self) -> Result<JoinHandle<()>, Box<dyn Error>> {
-        let hello_event = {
-            let event = self
+    // Stops early once shutdown is asked for, `receive` picks that up next.
+    async fn reconnect(
+        &self,
+        heartbeater: &mut JoinHandle<()>,
+        recover_data: Option<&RecoverData>,
+        reason: &str,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        metrics::reconnect(reason);
+        heartbeater.abort();
+        let mut attempt = 0;
+        loop {
+            let Some(connected) = self
                 .0
-                .connection
-                .recv()
+                .shutdown
+                .until_shutdown(self.connect(recover_data))
                 .await
-                .expect("Should not have closed")?;
-            from_str::<RawEvent>(&event)
-                .or(Err(()))
-                .and_then(|event| event.try_into_mature(self.client().clone()))
-                .expect("Should be valid event")
+            else {
+                return Ok(());
+            };
+            match connected {
+                Ok(new_heartbeater) => {
+                    *heartbeater = new_heartbeater;
+                    return Ok(());
+                }
+                Err(err) => {
+                    attempt += 1;
+                    let fatal = matches!(
+                        err.downcast_ref::<GatewayError>(),
+                        Some(GatewayError::Closed(code)) if code.is_fatal()
+                    );
+                    if fatal || self.0.reconnect.is_exhausted(attempt) {
+                        error!(attempt, error = %err, "Giving up reconnecting");
+                        return Err(err);
+                    }
+                    warn!(attempt, error = %err, "Failed to reconnect");
+                }
+            }
+            let backoff = tokio::time::sleep(self.0.reconnect.delay(attempt));
+            if self.0.shutdown.until_shutdown(backoff).await.is_none() {
+                return Ok(());
+            }
+        }
+    }
+
+    pub(crate) async fn run(self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let backlog = self.1.backlog.take().expect("Should only run once");
+        let processor = tokio::spawn(self.clone().process(backlog).in_current_span());
+        let res = match self.0.shutdown.until_shutdown(self.connect(None)).await {
+            Some(Ok(mut heartbeater)) => {
+                let res = self.receive(&mut heartbeater).await;
+                heartbeater.abort();
+                res
+            }
+            Some(Err(err)) => Err(err),
+            None => Ok(()),
         };
-        if let Event::Hello { mut heartbeat } = hello_event {
+        processor.abort();
+        if res.is_ok() {
+            info!("Shutting down");
+            let _ = self.1.connection.close().await;
+            let _ = tokio::time::timeout(self.0.shutdown_timeout, self.0.dispatcher.drain()).await;
+        } else {
+            // Fails the sends still waiting for a session to open.
+            let _ = self.1.connection.close().await;
+        }
+        res
+    }
+
+    // READY only knows about Discord's own gateways, a forced one is used for
+    // resuming too.
+    fn recover_data(&self, ready: &Value) -> Option<RecoverData> {
+        let resume_url = match self.0.gateway_url_forced {
+            true => self.0.gateway_url.clone(),
+            false => ready["resume_gateway_url"].as_str()?.into(),
+        };
+        Some(RecoverData {
+            session_id: ready["session_id"].as_str()?.into(),
+            resume_url,
+        })
+    }
+
+    // Only returns `Ok(())` once the bot has been asked to shut down. Handlers
+    // run on the processor, this only waits for them once the backlog is full.
+    async fn receive(
+        &self,
+        heartbeater: &mut JoinHandle<()>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut recover_data = None;
+        loop {
+            if self.0.shutdown.is_shutdown() {
+                return Ok(());
+            }
+            // The heartbeater only stops by itself once the connection is dead
+            // or has turned into a zombie.
+            let received = tokio::select! {
+                received = self.1.connection.recv() => received,
+                _ = &mut *heartbeater => Received::Closed(None),
+                _ = self.0.shutdown.wait() => return Ok(()),
+            };
+            let payload = match received {
+                Received::Payload(payload) => payload,
+                Received::Closed(Some(code)) if code.is_fatal() => {
+                    error!(close_code = %code, "Gateway closed the connection");
+                    return Err(GatewayError::Closed(code).into());
+                }
+                Received::Closed(code) => {
+                    match code {
+                        Some(code) => warn!(close_code = %code, "Gateway closed the connection"),
+                        None => warn!("Gateway connection was lost"),
+                    }
+                    if code.is_some_and(|code| !code.can_resume()) {
+                        recover_data = None;
+                    }
+                    let reason = match code {
+                        Some(code) => format!("close_{}", code.code()),
+                        None => "connection_lost".to_string(),
+                    };
+                    self.reconnect(heartbeater, recover_data.as_ref(), &reason)
+                        .await?;
+                    continue;
+                }
+                Received::Failed(err) => {
+                    warn!(error = %err, "Gateway connection failed");
+                    self.reconnect(heartbeater, recover_data.as_ref(), "connection_failed")
+                        .await?;
+                    continue;
+                }
+            };
+
+            // A payload this shard cannot make sense of is no reason to stop
+            // it, only close codes and the transport are.
+            let raw = match self.decode_event(&payload) {
+                Ok(raw) => raw,
+                Err(err) => {
+                    warn!(error = %err, "Skipping undecodable gateway payload");
+                    continue;
+                }
+            };
+            if raw.opcode() == 0 {
+                if let Some(sequence_number) = raw.sequence_number() {
+                    self.update_sequence_number(sequence_number).await;
+                }
+                if raw.event_name() == Some("READY") {
+                    info!("Session is ready");
+                    recover_data = self.recover_data(raw.data());
+                }
+                // A full backlog holds up reading until the processor catches up.
+                let pushed = self.1.backlog.push(raw);
+                if self.0.shutdown.until_shutdown(pushed).await.is_none() {
+                    return Ok(());
+                }
+                continue;
+            }
+            let opcode = raw.opcode();
+            let event = raw.clone().try_into_mature(self.client().clone());
+            let pushed = self.1.backlog.push(raw);
+            if self.0.shutdown.until_shutdown(pushed).await.is_none() {
+                return Ok(());
+            }
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    warn!(opcode, error = %err, "Skipping unknown gateway event");
+                    continue;
+                }
+            };
+            match event {
+                Event::Reconnect | Event::InvalidSession { resumable: true } => {
+                    info!("Gateway asked to reconnect");
+                    self.reconnect(heartbeater, recover_data.as_ref(), "requested")
+                        .await?;
+                }
+                Event::InvalidSession { resumable: false } => {
+                    warn!("Session was invalidated");
+                    recover_data = None;
+                    let wait = rand::thread_rng().gen_range(1000..=5000);
+                    let wait = tokio::time::sleep(Duration::from_millis(wait));
+                    if self.0.shutdown.until_shutdown(wait).await.is_none() {
+                        return Ok(());
+                    }
+                    self.reconnect(heartbeater, None, "invalid_session").await?;
+                }
+                Event::HeartbeatRequest => {
+                    self.1.heartbeat.beat_requested();
+                    if self.send_heartbeat().await.is_err() {
+                        self.reconnect(heartbeater, recover_data.as_ref(), "heartbeat_failed")
+                            .await?;
+                    }
+                }
+                Event::HeartbeatACK => {
+                    self.1.heartbeat.ack();
+                    let latency = self.1.heartbeat.latency();
+                    trace!(?latency, "Heartbeat acknowledged");
+                    if let Some(latency) = latency {
+                        metrics::heartbeat_latency(self.shard().id(), latency);
+                    }
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    // Takes payloads from the backlog one at a time, in the order they were
+    // received. A slow hook or a full dispatch queue holds up this first, and
+    // the reader only once the backlog has filled up.
+    async fn process(self, mut backlog: Receiver<RawEvent>) {
+        let mut ready_notified = false;
+        while let Some(raw) = backlog.recv().await {
+            self.clone()
+                .supervise("RAW_EVENT".into(), Impl::on_raw_event(self.clone(), &raw))
+                .await;
+            self.client().raw_cache().update(&raw);
+            if raw.opcode() != 0 {
+                continue;
+            }
+            let event = match raw.try_into_mature(self.client().clone()) {
+                Ok(Event::Dispatch { event, .. }) => event,
+                Ok(_) => continue,
+                Err(err) => {
+                    warn!(error = %err, "Dropping malformed gateway event");
+                    continue;
+                }
+            };
+            trace!(?event, "Received gateway event");
+            metrics::event_received(event.name());
             let bot = self.clone();
-            let heartbeater = tokio::spawn(async move {
-                heartbeat.tick().await;
+            let event = match event {
+                DispatchedEvent::GuildMembersChunk(chunk) => match self.0.members.collect(chunk) {
+                    Some(chunk) => DispatchedEvent::GuildMembersChunk(chunk),
+                    None => continue,
+                },
+                event => event,
+            };
+            let Some(event) = self.0.middleware.apply(self.shard(), event).await else {
+                continue;
+            };
+            if let Some(events) = &self.0.events {
+                // A full stream only holds up this processor. Once it has been
+                // dropped nobody is left to take events, so the bot stops.
+                if events.send((self.shard(), event)).await.is_err() {
+                    info!("Event stream was dropped");
+                    self.shutdown();
+                    break;
+                }
+                continue;
+            }
+            match event {
+                DispatchedEvent::Ready(_) if !ready_notified => {
+                    ready_notified = true;
+                    self.0
+                        .dispatcher
+                        .dispatch(
+                            None,
+                            None,
+                            bot.clone().supervise("READY".into(), Impl::on_ready(bot)),
+                        )
+                        .await;
+                }
+                DispatchedEvent::MessageCreated(msg) => {
+                    self.warn_missing_content();
+                    let guild_id = msg.message.guild_id().map(ToOwned::to_owned);
+                    let channel_id = msg.message.channel_id().to_owned();
+                    self.0
+                        .dispatcher
+                        .dispatch(
+                            guild_id.as_deref(),
+                            Some(&channel_id),
+                            bot.clone().supervise(
+                                "MESSAGE_CREATE".into(),
+                                Impl::on_message_created(bot, msg),
+                            ),
+                        )
+                        .await;
+                }
+                DispatchedEvent::GuildMembersChunk(chunk) => {
+                    let guild_id = chunk.guild_id.clone();
+                    self.0
+                        .dispatcher
+                        .dispatch(
+                            Some(&guild_id),
+                            None,
+                            bot.clone().supervise(
+                                "GUILD_MEMBERS_CHUNK".into(),
+                                Impl::on_guild_members_chunk(bot, chunk),
+                            ),
+                        )
+                        .await;
+                }
+                DispatchedEvent::Unknown { event_name, data } => {
+                    let id =
+                        |field: &str| data[field].as_str().map(|id| ID::from_raw(id).to_owned());
+                    let (guild_id, channel_id) = (id("guild_id"), id("channel_id"));
+                    self.0
+                        .dispatcher
+                        .dispatch(
+                            guild_id.as_deref(),
+                            channel_id.as_deref(),
+                            bot.clone().supervise(
+                                event_name.clone(),
+                                Impl::on_unknown_event(bot, event_name, data),
+                            ),
+                        )
+                        .await;
+                }
+                _ => continue,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{convert::Infallible, error::Error, time::Duration};
+
+    use futures::future;
+    use serde_json::json;
+    use tokio::sync::mpsc::{self, UnboundedSender};
+
+    use super::Bot;
+    use crate::{
+        bot::{BlanketImpl, DispatchMode},
+        discord::gateway::MessageCreatedEvent,
+        prelude::{BotImpl, Intents},
+        testing::{step_paused_time, MockDiscord},
+    };
+
+    #[tokio::test]
+    async fn heartbeat_request() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::with_heartbeat_interval(60_000).await?;
+        let bot = mock
+            .template()
+            .intents(Intents::GUILDS)
+            .implement(BlanketImpl, mock.token());
+        let heartbeat = mock
+            .run_until(bot, async {
+                mock.gateway.request_heartbeat();
+                tokio::time::timeout(Duration::from_secs(1), mock.gateway.wait_for_op(1))
+                    .await
+                    .expect("Should respond to heartbeat request immediately")
+                    .expect("Should be running")
+            })
+            .await;
+        // READY was the last event received.
+        assert_eq!(heartbeat["d"], 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resume_after_reconnect() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        let bot = mock
+            .template()
+            .intents(Intents::GUILDS)
+            .implement(BlanketImpl, mock.token());
+        let resume = mock
+            .run_until(bot, async {
+                mock.gateway.request_reconnect();
+                mock.gateway.wait_for_op(6).await.expect("Should resume")
+            })
+            .await;
+        assert_eq!(resume["d"]["token"], MockDiscord::TOKEN);
+        assert_eq!(resume["d"]["session_id"], "mock-session-0");
+        assert_eq!(resume["d"]["seq"], 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fatal_close_code() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        let bot = mock
+            .template()
+            .intents(Intents::GUILDS)
+            .implement(BlanketImpl, mock.token());
+        let script = async {
+            mock.gateway.wait_for_op(2).await;
+            mock.gateway.close(4004);
+            std::future::pending::<()>().await
+        };
+
+        let err = tokio::select! {
+            res = bot => res.expect_err("Bot should stop"),
+            _ = script => unreachable!(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Gateway closed the connection with 4004 (authentication failed)"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reidentify_after_session_timeout() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        let bot = mock
+            .template()
+            .intents(Intents::GUILDS)
+            .implement(BlanketImpl, mock.token());
+        let payload = mock
+            .run_until(bot, async {
+                mock.gateway.close(4009);
                 loop {
-                    heartbeat.tick().await;
-                    let seq_num = *bot.0.last_sequence_number.lock().await;
-                    let data = to_value(seq_num).expect("Should be valid");
-                    let heartbeat_event = RawEvent::new(1, data);
-                    bot.0
-                        .connection
-                        .send(to_string(&heartbeat_event).expect("Should succeed"))
+                    let payload = mock
+                        .gateway
+                        .next_payload()
                         .await
-                        .expect("should not closed");
+                        .expect("Should be running");
+                    if payload["op"] == 2 || payload["op"] == 6 {
+                        return payload;
+                    }
                 }
-            });
-            Ok(heartbeater)
-        } else {
-            unimplemented!(); // TODO: Return error
+            })
+            .await;
+        assert_eq!(payload["op"], 2);
+        Ok(())
+    }
+
+    struct Echo(UnboundedSender<String>);
+
+    impl BotImpl for Echo {
+        type Error = Infallible;
+
+        async fn on_message_created(
+            bot: Bot<Self>,
+            msg: MessageCreatedEvent,
+        ) -> Result<(), Infallible> {
+            let content = msg.message.content().unwrap_or_default().to_string();
+            let _ = bot.implementation().0.send(content);
+            Ok(())
         }
     }
 
-    async fn resume_data(&self, resume_data: &RecoverData) -> String {
-        let seq_num = self
-            .get_seqenuce_number()
-            .await
-            .map(|num| num.to_string())
-            .unwrap_or_else(|| "null".to_string());
-        let mut data = String::with_capacity(10);
-        data.push_str("{\"op\":6,\"d\":{\"token\":\"");
-        data.push_str(self.token());
-        data.push_str("\",\"session_id\":\"");
-        data.push_str(&resume_data.session_id);
-        data.push_str("\",\"seq\":");
-        data.push_str(&seq_num);
-        data
-    }
-
-    async fn resume(
-        &self,
-        heartbeater: &mut JoinHandle<()>,
-        recover_data: &RecoverData,
-    ) -> Result<(), Box<dyn Error>> {
-        heartbeater.abort();
-        self.0
-            .connection
-            .change_socket(&recover_data.resume_url)
-            .await?;
-        *heartbeater = self.hello().await?;
-        let resume_data = self.resume_data(recover_data).await;
-        self.0.connection.send(resume_data).await?;
+    #[tokio::test]
+    async fn skip_unknown_opcode() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        let (sender, mut seen) = mpsc::unbounded_channel();
+        let bot = mock
+            .template()
+            .intents(Intents::GUILDS | Intents::GUILD_MESSAGES | Intents::MESSAGE_CONTENT)
+            .implement(Echo(sender), mock.token());
+        let content = mock
+            .run_until(bot, async {
+                mock.gateway.send(json!({ "op": 42, "d": null }));
+                mock.message_create("4000", "2000", "still here");
+                seen.recv().await.expect("Should be running")
+            })
+            .await;
+        assert_eq!(content, "still here");
+        assert_eq!(mock.gateway.connections(), 1);
         Ok(())
     }
 
-    pub(crate) async fn run(self) -> Result<(), Box<dyn Error>> {
-        let mut heartbeater = self.hello().await?;
-        let recover_data = self.ready().await?;
-        loop {
-            let event_str = self
-                .0
-                .connection
-                .recv()
-                .await
-                .expect("Should succeed")
-                .expect("Should succeed");
-
-            let event = serde_json::from_str::<RawEvent>(&event_str)
-                .expect("Should succeed")
-                .try_into_mature(self.client().clone())
-                .expect("should be valid event");
-            dbg!(&event);
-            if let Event::Dispatch {
-                sequence_number,
-                event,
-            } = event
-            {
-                let bot = self.clone();
-                self.update_sequence_number(sequence_number).await;
-                tokio::spawn(match event {
-                    DispatchedEvent::MessageCreated(msg) => Impl::on_message_created(bot, msg),
-                    _ => continue,
-                });
-            } else if let Event::Reconnect = event {
-                self.resume(&mut heartbeater, &recover_data).await?;
-            }
+    struct StuckBot;
+
+    impl BotImpl for StuckBot {
+        type Error = Infallible;
+
+        async fn on_message_created(
+            _: Bot<Self>,
+            _: MessageCreatedEvent,
+        ) -> Result<(), Infallible> {
+            future::pending().await
         }
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn heartbeats_while_handlers_are_stuck() -> Result<(), Box<dyn Error>> {
+        const HEARTBEAT_INTERVAL: u64 = 2_500;
+        let mock = MockDiscord::with_heartbeat_interval(HEARTBEAT_INTERVAL).await?;
+        tokio::spawn(step_paused_time(Duration::from_millis(25)));
+        let bot = mock
+            .template()
+            .intents(Intents::GUILDS | Intents::GUILD_MESSAGES | Intents::MESSAGE_CONTENT)
+            .dispatch_mode(DispatchMode::sequential().queue_size(1))
+            .implement(StuckBot, mock.token());
+        let heartbeats = mock
+            .run_until(bot, async {
+                // The first handler never finishes and the second fills the
+                // queue, so the third cannot be dispatched.
+                for id in ["4000", "4001", "4002"] {
+                    mock.message_create(id, "2000", "hi");
+                }
+                let mut heartbeats = 0;
+                let _ =
+                    tokio::time::timeout(Duration::from_millis(HEARTBEAT_INTERVAL * 4), async {
+                        while mock.gateway.wait_for_op(1).await.is_some() {
+                            heartbeats += 1;
+                        }
+                    })
+                    .await;
+                heartbeats
+            })
+            .await;
+        // A heartbeater whose ACKs go unread stops after its second beat.
+        assert!(heartbeats >= 3, "Only {} heartbeats were sent", heartbeats);
+        assert_eq!(mock.gateway.connections(), 1);
+        Ok(())
+    }
 }
@@ -0,0 +1,264 @@
+use std::{collections::HashMap, error::Error, fmt::Display, sync::Mutex};
+
+use tokio::sync::oneshot;
+use tokio_tungstenite::tungstenite::Error as WsError;
+
+use crate::{
+    discord::{gateway::GuildMembersChunkEvent, Member},
+    prelude::*,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemberQuery {
+    Query { query: Box<str>, limit: u32 },
+    UserIds(Vec<OwnedID>),
+}
+
+impl MemberQuery {
+    #[inline]
+    pub fn all() -> Self {
+        Self::Query {
+            query: "".into(),
+            limit: 0,
+        }
+    }
+
+    #[inline]
+    pub fn starts_with(query: impl Into<Box<str>>, limit: u32) -> Self {
+        Self::Query {
+            query: query.into(),
+            limit,
+        }
+    }
+
+    #[inline]
+    pub fn user_ids(user_ids: impl IntoIterator<Item = OwnedID>) -> Self {
+        Self::UserIds(user_ids.into_iter().collect())
+    }
+}
+
+#[derive(Debug)]
+pub enum RequestMembersError {
+    MissingIntent(Intents),
+    InvalidGuildId(OwnedID),
+    ShardNotRunning(OwnedID),
+    Send(WsError),
+    ShardClosed,
+    Timeout,
+}
+
+impl Display for RequestMembersError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use RequestMembersError::*;
+        match self {
+            MissingIntent(intents) => write!(f, "Missing intents {:?}", intents),
+            InvalidGuildId(id) => write!(f, "Invalid guild id {}", id),
+            ShardNotRunning(id) => write!(f, "The shard of guild {} is not running", id),
+            Send(err) => write!(f, "Cannot send member request: {}", err),
+            ShardClosed => f.write_str("The shard stopped before guild members arrived"),
+            Timeout => f.write_str("Timed out waiting for guild members"),
+        }
+    }
+}
+
+impl Error for RequestMembersError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Send(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<WsError> for RequestMembersError {
+    fn from(value: WsError) -> Self {
+        Self::Send(value)
+    }
+}
+
+struct PendingMembers {
+    members: Vec<Member>,
+    received: u32,
+    sender: oneshot::Sender<Vec<Member>>,
+}
+
+#[derive(Default)]
+pub(crate) struct MemberCollector(Mutex<HashMap<Box<str>, PendingMembers>>);
+
+impl MemberCollector {
+    pub(crate) fn register(&self, nonce: Box<str>) -> oneshot::Receiver<Vec<Member>> {
+        let (sender, recver) = oneshot::channel();
+        let pending = PendingMembers {
+            members: Vec::new(),
+            received: 0,
+            sender,
+        };
+        self.lock().insert(nonce, pending);
+        recver
+    }
+
+    pub(crate) fn cancel(&self, nonce: &str) {
+        self.lock().remove(nonce);
+    }
+
+    // Hands the chunk back when no request of ours is waiting for it, so it
+    // can go to `on_guild_members_chunk` or the event stream instead.
+    pub(crate) fn collect(&self, chunk: GuildMembersChunkEvent) -> Option<GuildMembersChunkEvent> {
+        let mut requests = self.lock();
+        let Some(pending) = chunk
+            .nonce
+            .as_deref()
+            .and_then(|nonce| requests.get_mut(nonce))
+        else {
+            return Some(chunk);
+        };
+        pending.members.extend(chunk.members);
+        pending.received += 1;
+        if pending.received >= chunk.chunk_count {
+            let nonce = chunk.nonce.expect("Should be pending");
+            let pending = requests.remove(&nonce).expect("Should be pending");
+            let _ = pending.sender.send(pending.members);
+        }
+        None
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Box<str>, PendingMembers>> {
+        self.0.lock().expect("Should not be poisoned")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{convert::Infallible, error::Error};
+
+    use serde_json::{json, Value};
+    use tokio::sync::mpsc::{self, UnboundedSender};
+
+    use super::{MemberCollector, MemberQuery};
+    use crate::{
+        bot::client::DiscordClient,
+        discord::gateway::{GuildMembersChunkEvent, RawGuildMembersChunk},
+        prelude::{Bot, BotImpl, Intents, ID},
+        testing::MockDiscord,
+    };
+
+    fn chunk(nonce: Value, index: u32, count: u32, user_id: &str) -> GuildMembersChunkEvent {
+        let raw: RawGuildMembersChunk = serde_json::from_value(json!({
+            "guild_id": "5000",
+            "members": [{ "user": { "id": user_id, "username": "someone" } }],
+            "chunk_index": index,
+            "chunk_count": count,
+            "nonce": nonce,
+        }))
+        .unwrap();
+        raw.to_mature(DiscordClient::from_raw(reqwest::Client::new(), 10))
+    }
+
+    #[test]
+    fn collect_by_chunk_count() {
+        let collector = MemberCollector::default();
+        let mut members = collector.register("nonce".into());
+
+        assert!(collector
+            .collect(chunk(json!("nonce"), 0, 2, "3000"))
+            .is_none());
+        assert!(members.try_recv().is_err());
+        assert!(collector
+            .collect(chunk(json!("nonce"), 1, 2, "3001"))
+            .is_none());
+        let ids: Vec<_> = members
+            .try_recv()
+            .unwrap()
+            .iter()
+            .map(|member| member.user().unwrap().id().to_string())
+            .collect();
+        assert_eq!(ids, ["3000", "3001"]);
+
+        // The request is done, so later chunks with its nonce are not ours.
+        assert!(collector
+            .collect(chunk(json!("nonce"), 2, 3, "3002"))
+            .is_some());
+    }
+
+    #[test]
+    fn forward_unrequested() {
+        let collector = MemberCollector::default();
+        let _members = collector.register("nonce".into());
+
+        let forwarded = collector.collect(chunk(Value::Null, 0, 1, "3000"));
+        assert_eq!(forwarded.unwrap().nonce, None);
+        let forwarded = collector.collect(chunk(json!("other"), 0, 1, "3000"));
+        assert_eq!(forwarded.unwrap().nonce.as_deref(), Some("other"));
+    }
+
+    struct Requester(UnboundedSender<String>);
+
+    impl BotImpl for Requester {
+        type Error = Infallible;
+
+        async fn on_ready(bot: Bot<Self>) -> Result<(), Infallible> {
+            let requested = bot
+                .request_guild_members(ID::from_raw("5000"), MemberQuery::all(), false)
+                .await;
+            let seen = match requested {
+                Ok(members) => {
+                    let ids: Vec<_> = members
+                        .iter()
+                        .map(|member| member.user().unwrap().id().to_string())
+                        .collect();
+                    format!("requested {}", ids.join(","))
+                }
+                Err(err) => format!("failed {}", err),
+            };
+            let _ = bot.implementation().0.send(seen);
+            Ok(())
+        }
+
+        async fn on_guild_members_chunk(
+            bot: Bot<Self>,
+            chunk: GuildMembersChunkEvent,
+        ) -> Result<(), Infallible> {
+            let _ = bot
+                .implementation()
+                .0
+                .send(format!("chunk {}", chunk.members.len()));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn request_over_gateway() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        let (sender, mut seen) = mpsc::unbounded_channel();
+        let bot = mock
+            .template()
+            .intents(Intents::GUILDS | Intents::GUILD_MEMBERS)
+            .implement(Requester(sender), mock.token());
+        let mut seen = mock
+            .run_until(bot, async {
+                let request = mock.gateway.wait_for_op(8).await.expect("Should request");
+                assert_eq!(request["d"]["guild_id"], "5000");
+                let nonce = request["d"]["nonce"].clone();
+                let chunk = |index: u32, user_id: &str, nonce: &Value| {
+                    json!({
+                        "guild_id": "5000",
+                        "members": [{ "user": { "id": user_id, "username": "someone" } }],
+                        "chunk_index": index,
+                        "chunk_count": 2,
+                        "nonce": nonce,
+                    })
+                };
+                mock.gateway
+                    .dispatch("GUILD_MEMBERS_CHUNK", chunk(0, "3999", &Value::Null));
+                mock.gateway
+                    .dispatch("GUILD_MEMBERS_CHUNK", chunk(0, "3000", &nonce));
+                mock.gateway
+                    .dispatch("GUILD_MEMBERS_CHUNK", chunk(1, "3001", &nonce));
+                vec![seen.recv().await.unwrap(), seen.recv().await.unwrap()]
+            })
+            .await;
+        seen.sort();
+        assert_eq!(seen, ["chunk 1", "requested 3000,3001"]);
+        Ok(())
+    }
+}
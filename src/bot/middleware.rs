@@ -0,0 +1,189 @@
+use std::{fmt::Debug, panic::AssertUnwindSafe};
+
+use tracing::error;
+
+use super::{handler::HandlerError, ShardId};
+use crate::{discord::gateway::DispatchedEvent, prelude::*};
+
+// Returning `None` drops the event, nothing after this middleware sees it.
+pub trait Middleware: Send + Sync + 'static {
+    fn handle(
+        &self,
+        shard: ShardId,
+        event: DispatchedEvent,
+    ) -> impl Future<Output = Option<DispatchedEvent>> + Send;
+}
+
+impl<F, Fut> Middleware for F
+where
+    F: Fn(ShardId, DispatchedEvent) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Option<DispatchedEvent>> + Send,
+{
+    #[inline]
+    fn handle(
+        &self,
+        shard: ShardId,
+        event: DispatchedEvent,
+    ) -> impl Future<Output = Option<DispatchedEvent>> + Send {
+        self(shard, event)
+    }
+}
+
+trait ErasedMiddleware: Send + Sync {
+    fn handle<'a>(
+        &'a self,
+        shard: ShardId,
+        event: DispatchedEvent,
+    ) -> std::pin::Pin<Box<dyn Future<Output = Option<DispatchedEvent>> + Send + 'a>>;
+}
+
+impl<M: Middleware> ErasedMiddleware for M {
+    #[inline]
+    fn handle<'a>(
+        &'a self,
+        shard: ShardId,
+        event: DispatchedEvent,
+    ) -> std::pin::Pin<Box<dyn Future<Output = Option<DispatchedEvent>> + Send + 'a>> {
+        Box::pin(Middleware::handle(self, shard, event))
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct MiddlewareChain(Vec<Box<dyn ErasedMiddleware>>);
+
+impl MiddlewareChain {
+    #[inline]
+    pub(crate) fn push(&mut self, middleware: impl Middleware) {
+        self.0.push(Box::new(middleware));
+    }
+
+    // Runs in registration order on the shard's processor, so every event goes
+    // through the whole chain before the next one is processed.
+    pub(crate) async fn apply(
+        &self,
+        shard: ShardId,
+        mut event: DispatchedEvent,
+    ) -> Option<DispatchedEvent> {
+        for middleware in &self.0 {
+            let name: Box<str> = event.name().into();
+            match AssertUnwindSafe(middleware.handle(shard, event))
+                .catch_unwind()
+                .await
+            {
+                Ok(Some(next)) => event = next,
+                Ok(None) => return None,
+                Err(panic) => {
+                    let error = HandlerError::from_panic(panic);
+                    error!(event = %name, %shard, %error, "Middleware panicked, dropping event");
+                    return None;
+                }
+            }
+        }
+        Some(event)
+    }
+}
+
+impl Debug for MiddlewareChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("MiddlewareChain")
+            .field(&self.0.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::error::Error;
+
+    use futures::StreamExt;
+    use serde_json::json;
+
+    use super::MiddlewareChain;
+    use crate::{
+        bot::ShardId, discord::gateway::DispatchedEvent, prelude::*, testing::MockDiscord,
+    };
+
+    fn typing(channel_id: &str) -> DispatchedEvent {
+        DispatchedEvent::Unknown {
+            event_name: "TYPING_START".into(),
+            data: json!({ "channel_id": channel_id }),
+        }
+    }
+
+    #[tokio::test]
+    async fn chain_order() {
+        let mut chain = MiddlewareChain::default();
+        chain.push(|_, event| async move {
+            match event {
+                DispatchedEvent::Unknown {
+                    event_name,
+                    mut data,
+                } => {
+                    data["seen"] = json!(true);
+                    Some(DispatchedEvent::Unknown { event_name, data })
+                }
+                event => Some(event),
+            }
+        });
+        chain.push(|_, event: DispatchedEvent| async move {
+            match &event {
+                DispatchedEvent::Unknown { data, .. } if data["channel_id"] == "blocked" => None,
+                _ => Some(event),
+            }
+        });
+
+        let shard = ShardId::new(0, 1);
+        assert!(chain.apply(shard, typing("blocked")).await.is_none());
+        match chain.apply(shard, typing("2000")).await {
+            Some(DispatchedEvent::Unknown { data, .. }) => assert_eq!(data["seen"], true),
+            event => panic!("Unexpected event: {:?}", event),
+        }
+    }
+
+    #[tokio::test]
+    async fn panic_drops_event() {
+        let mut chain = MiddlewareChain::default();
+        chain.push(|_, _| async { panic!("Middleware gave up") });
+        assert!(chain
+            .apply(ShardId::new(0, 1), typing("2000"))
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn drop_bot_messages() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        let (_, mut events) = mock
+            .template()
+            .intents(Intents::GUILDS | Intents::GUILD_MESSAGES | Intents::MESSAGE_CONTENT)
+            .middleware(|_, event| async move {
+                match &event {
+                    DispatchedEvent::Ready(_) => None,
+                    DispatchedEvent::MessageCreated(msg) if msg.message.author().is_bot() => None,
+                    _ => Some(event),
+                }
+            })
+            .connect(mock.token())
+            .await?;
+        mock.gateway.wait_for_op(2).await;
+
+        mock.gateway.dispatch(
+            "MESSAGE_CREATE",
+            json!({
+                "id": "4000",
+                "channel_id": "2000",
+                "author": { "id": "3001", "username": "robot", "bot": true },
+                "content": "hi",
+                "tts": false,
+            }),
+        );
+        mock.message_create("4001", "2000", "hi");
+        match events.next().await.expect("Should receive a message") {
+            (_, DispatchedEvent::MessageCreated(msg)) => {
+                assert_eq!(msg.message.id(), ID::from_raw("4001"))
+            }
+            (_, event) => panic!("Unexpected event: {:?}", event),
+        }
+        Ok(())
+    }
+}
@@ -1,11 +1,34 @@
+mod backlog;
+mod cache;
 pub(crate) mod client;
 mod command;
 mod connection;
+mod dispatcher;
+mod events;
+mod handler;
+mod heartbeat;
+mod identify_queue;
 mod implementation;
 mod main;
+mod members;
+mod middleware;
+mod reconnect;
+mod send_queue;
+mod shard;
+mod shutdown;
 mod template;
 
+pub use cache::Cache;
 pub use command::*;
+pub use connection::{Compression, Encoding};
+pub use dispatcher::{DispatchKey, DispatchMode};
+pub use events::*;
+pub use handler::*;
 pub use implementation::*;
 pub use main::*;
+pub use members::*;
+pub use middleware::Middleware;
+pub use reconnect::*;
+pub use shard::*;
+pub use shutdown::ShutdownHandle;
 pub use template::*;
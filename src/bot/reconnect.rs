@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectConfig {
+    pub(crate) initial_delay: Duration,
+    pub(crate) max_delay: Duration,
+    pub(crate) multiplier: f64,
+    pub(crate) jitter: f64,
+    pub(crate) max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.5,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectConfig {
+    #[inline]
+    pub fn initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    #[inline]
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    #[inline]
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    #[inline]
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    #[inline]
+    pub fn max_attempts(mut self, max_attempts: Option<u32>) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub(crate) fn is_exhausted(&self, attempt: u32) -> bool {
+        self.max_attempts.is_some_and(|max| attempt >= max)
+    }
+
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let exp = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        let delay = Duration::from_secs_f64(
+            (self.initial_delay.as_secs_f64() * exp).min(self.max_delay.as_secs_f64()),
+        );
+        let jitter = rand::thread_rng().gen_range(0.0..=self.jitter);
+        delay.mul_f64(1.0 - jitter)
+    }
+}
@@ -0,0 +1,127 @@
+use std::{collections::VecDeque, sync::Mutex as StdMutex, time::Duration};
+
+use tokio::{
+    sync::{Mutex, Notify},
+    time::Instant,
+};
+
+use crate::metrics;
+
+const WINDOW: Duration = Duration::from_secs(60);
+const LIMIT: usize = 120;
+// Sends kept free for heartbeats, which must go out even when the bot is
+// flooding the gateway with other payloads.
+const HEARTBEAT_HEADROOM: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Priority {
+    Heartbeat,
+    // Identify and resume, which everything but heartbeats waits for.
+    Identify,
+    Normal,
+}
+
+impl Priority {
+    fn limit(&self) -> usize {
+        match self {
+            Self::Heartbeat | Self::Identify => LIMIT,
+            Self::Normal => LIMIT - HEARTBEAT_HEADROOM,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct SendQueue {
+    sent: StdMutex<VecDeque<Instant>>,
+    // Tokio's mutex is fair, so normal payloads leave the queue in the order
+    // they entered it. Heartbeats, identify and resume never wait on it.
+    queue: Mutex<()>,
+    reset: Notify,
+}
+
+impl SendQueue {
+    pub(crate) async fn acquire(&self, priority: Priority) {
+        let started = Instant::now();
+        let _turn = match priority {
+            Priority::Heartbeat | Priority::Identify => None,
+            Priority::Normal => Some(self.queue.lock().await),
+        };
+        loop {
+            let next_slot = {
+                let mut sent = self.sent.lock().expect("Should not be poisoned");
+                let now = Instant::now();
+                while sent.front().is_some_and(|at| now - *at >= WINDOW) {
+                    sent.pop_front();
+                }
+                if sent.len() < priority.limit() {
+                    sent.push_back(now);
+                    if now > started {
+                        metrics::rate_limit_wait(now - started);
+                    }
+                    return;
+                }
+                sent[sent.len() - priority.limit()] + WINDOW
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(next_slot) => {}
+                _ = self.reset.notified() => {}
+            }
+        }
+    }
+
+    // The limit applies per connection, so a fresh socket starts with an
+    // empty window.
+    pub(crate) fn reset(&self) {
+        self.sent.lock().expect("Should not be poisoned").clear();
+        self.reset.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{Priority, SendQueue, HEARTBEAT_HEADROOM, LIMIT, WINDOW};
+
+    #[tokio::test(start_paused = true)]
+    async fn heartbeat_headroom() {
+        let queue = SendQueue::default();
+        let start = Instant::now();
+        for _ in 0..LIMIT - HEARTBEAT_HEADROOM {
+            queue.acquire(Priority::Normal).await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        for _ in 0..HEARTBEAT_HEADROOM {
+            queue.acquire(Priority::Heartbeat).await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        queue.acquire(Priority::Normal).await;
+        assert!(start.elapsed() >= WINDOW);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn heartbeat_skips_queue() {
+        let queue = std::sync::Arc::new(SendQueue::default());
+        for _ in 0..LIMIT - HEARTBEAT_HEADROOM {
+            queue.acquire(Priority::Normal).await;
+        }
+        let waiting = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.acquire(Priority::Normal).await }
+        });
+        tokio::task::yield_now().await;
+
+        let start = Instant::now();
+        queue.acquire(Priority::Heartbeat).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert!(!waiting.is_finished());
+
+        queue.reset();
+        waiting.await.unwrap();
+        assert!(start.elapsed() < WINDOW);
+    }
+}
@@ -0,0 +1,150 @@
+use std::{error::Error, fmt::Display, ops::Range, sync::Arc};
+
+use tokio::{sync::Mutex, task::JoinSet};
+use tracing::{info_span, Instrument};
+
+use super::{backlog::Backlog, connection::Connection, heartbeat::HeartbeatTracker, RawBot};
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ShardId {
+    id: u32,
+    total: u32,
+}
+
+impl ShardId {
+    #[inline]
+    pub fn new(id: u32, total: u32) -> Self {
+        Self { id, total }
+    }
+
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    #[inline]
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    #[inline]
+    pub fn for_guild(guild_id: u64, total: u32) -> Self {
+        Self::new(((guild_id >> 22) % total.max(1) as u64) as u32, total)
+    }
+
+    #[inline]
+    pub(crate) fn as_array(&self) -> [u32; 2] {
+        [self.id, self.total]
+    }
+}
+
+impl Display for ShardId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}, {}]", self.id, self.total)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShardConfig {
+    Auto,
+    Fixed(u32),
+    Range { shards: Range<u32>, total: u32 },
+}
+
+impl Default for ShardConfig {
+    fn default() -> Self {
+        Self::Fixed(1)
+    }
+}
+
+impl ShardConfig {
+    pub(crate) fn resolve(&self, recommended: u32) -> Vec<ShardId> {
+        match self {
+            Self::Auto => (0..recommended.max(1))
+                .map(|id| ShardId::new(id, recommended.max(1)))
+                .collect(),
+            Self::Fixed(total) => (0..*total).map(|id| ShardId::new(id, *total)).collect(),
+            Self::Range { shards, total } => shards
+                .clone()
+                .filter(|id| id < total)
+                .map(|id| ShardId::new(id, *total))
+                .collect(),
+        }
+    }
+}
+
+pub(crate) struct Shard {
+    pub(super) id: ShardId,
+    pub(super) connection: Connection,
+    pub(super) last_sequence_number: Mutex<Option<usize>>,
+    pub(super) heartbeat: HeartbeatTracker,
+    pub(super) backlog: Backlog,
+}
+
+impl Shard {
+    pub(crate) fn new(id: ShardId, connection: Connection) -> Self {
+        Self {
+            id,
+            connection,
+            last_sequence_number: Mutex::new(None),
+            heartbeat: HeartbeatTracker::default(),
+            backlog: Backlog::default(),
+        }
+    }
+}
+
+pub(crate) struct ShardManager<Impl> {
+    bot: Arc<RawBot<Impl>>,
+    shards: Vec<ShardId>,
+}
+
+impl<Impl> ShardManager<Impl>
+where
+    Impl: BotImpl + Send + Sync,
+{
+    pub(crate) fn new(bot: RawBot<Impl>, shards: Vec<ShardId>) -> Self {
+        Self {
+            bot: Arc::new(bot),
+            shards,
+        }
+    }
+
+    // Every shard connects on its own task, so they all queue for identifying
+    // at once and the caller gets a handle right away.
+    pub(crate) fn start(self) -> (Vec<Bot<Impl>>, RunningShards) {
+        let mut running = JoinSet::new();
+        let mut bots = Vec::with_capacity(self.shards.len());
+        for id in self.shards {
+            let span = info_span!("shard", id = %id);
+            let connection = Connection::new(self.bot.compression);
+            let shard = Arc::new(Shard::new(id, connection));
+            self.bot
+                .running_shards
+                .lock()
+                .expect("Should not be poisoned")
+                .insert(id.id(), shard.clone());
+            let bot = Bot::from_raw(self.bot.clone(), shard);
+            bots.push(bot.clone());
+            running.spawn(bot.run().instrument(span));
+        }
+        (bots, RunningShards(running))
+    }
+
+    #[inline]
+    pub(crate) async fn run(self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (_, running) = self.start();
+        running.join().await
+    }
+}
+
+pub(crate) struct RunningShards(JoinSet<Result<(), Box<dyn Error + Send + Sync>>>);
+
+impl RunningShards {
+    pub(crate) async fn join(mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        while let Some(finished) = self.0.join_next().await {
+            finished??;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use tokio::sync::{watch, Notify};
+use tracing::warn;
+
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle(Arc<watch::Sender<bool>>);
+
+impl Default for ShutdownHandle {
+    fn default() -> Self {
+        Self(Arc::new(watch::Sender::new(false)))
+    }
+}
+
+impl ShutdownHandle {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn shutdown(&self) {
+        self.0.send_replace(true);
+    }
+
+    #[inline]
+    pub fn is_shutdown(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    pub async fn wait(&self) {
+        let mut receiver = self.0.subscribe();
+        let _ = receiver.wait_for(|shutdown| *shutdown).await;
+    }
+
+    // Resolves to `None` instead if shutdown is asked for first.
+    pub(crate) async fn until_shutdown<T>(&self, future: impl Future<Output = T>) -> Option<T> {
+        tokio::select! {
+            output = future => Some(output),
+            _ = self.wait() => None,
+        }
+    }
+
+    // Falls back to ctrl-c alone where SIGTERM cannot be listened for.
+    pub(crate) fn listen_for_signals(&self) {
+        let handle = self.clone();
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            {
+                use tokio::signal::unix::{signal, SignalKind};
+                match signal(SignalKind::terminate()) {
+                    Ok(mut terminate) => tokio::select! {
+                        _ = ctrl_c() => {}
+                        _ = terminate.recv() => {}
+                    },
+                    Err(err) => {
+                        warn!(error = %err, "Cannot listen for SIGTERM");
+                        ctrl_c().await;
+                    }
+                }
+            }
+            #[cfg(not(unix))]
+            ctrl_c().await;
+            handle.shutdown();
+        });
+    }
+}
+
+// Never resolves if ctrl-c cannot be listened for, rather than shutting down
+// right away.
+async fn ctrl_c() {
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        warn!(error = %err, "Cannot listen for ctrl-c");
+        std::future::pending::<()>().await;
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct HandlerTracker {
+    running: AtomicUsize,
+    idle: Notify,
+}
+
+struct HandlerGuard(Arc<HandlerTracker>);
+
+impl Drop for HandlerGuard {
+    fn drop(&mut self) {
+        if self.0.running.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+impl HandlerTracker {
+    pub(crate) fn spawn<F>(self: &Arc<Self>, handler: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.running.fetch_add(1, Ordering::AcqRel);
+        let guard = HandlerGuard(self.clone());
+        tokio::spawn(async move {
+            let _guard = guard;
+            handler.await;
+        });
+    }
+
+    pub(crate) async fn drain(&self) {
+        loop {
+            let idle = self.idle.notified();
+            tokio::pin!(idle);
+            idle.as_mut().enable();
+            if self.running.load(Ordering::Acquire) == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{convert::Infallible, error::Error, time::Duration};
+
+    use tokio::sync::mpsc::{self, UnboundedSender};
+
+    use super::ShutdownHandle;
+    use crate::{
+        bot::BlanketImpl, discord::gateway::MessageCreatedEvent, prelude::*, testing::MockDiscord,
+    };
+
+    struct SlowBot(UnboundedSender<&'static str>);
+
+    impl BotImpl for SlowBot {
+        type Error = Infallible;
+
+        async fn on_message_created(
+            bot: Bot<Self>,
+            _: MessageCreatedEvent,
+        ) -> Result<(), Infallible> {
+            let _ = bot.implementation().0.send("started");
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let _ = bot.implementation().0.send("finished");
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn graceful_shutdown() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        let shutdown = ShutdownHandle::new();
+        let (sender, mut handled) = mpsc::unbounded_channel();
+        let bot = mock
+            .template()
+            .intents(Intents::GUILDS)
+            .shutdown_handle(shutdown.clone())
+            .implement(SlowBot(sender), mock.token());
+        let script = async {
+            mock.gateway.wait_for_op(2).await;
+            mock.message_create("4000", "2000", "hi");
+            assert_eq!(handled.recv().await, Some("started"));
+            shutdown.shutdown();
+        };
+
+        let (res, ()) = tokio::join!(bot, script);
+        res?;
+        assert_eq!(handled.try_recv(), Ok("finished"));
+        assert_eq!(mock.gateway.wait_for_close().await, Some(Some(1000)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn shutdown_before_reidentifying() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        let shutdown = ShutdownHandle::new();
+        let bot = mock
+            .template()
+            .intents(Intents::GUILDS)
+            .shutdown_handle(shutdown.clone())
+            .implement(BlanketImpl, mock.token());
+        let script = async {
+            mock.gateway.wait_for_op(2).await;
+            // The shard waits at least a second before identifying again.
+            mock.gateway.invalidate_session(false);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            shutdown.shutdown();
+        };
+
+        let (res, ()) = tokio::time::timeout(Duration::from_millis(900), async {
+            tokio::join!(bot, script)
+        })
+        .await
+        .expect("Should stop without waiting to identify");
+        res?;
+        Ok(())
+    }
+}
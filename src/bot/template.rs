@@ -1,17 +1,47 @@
-use serde_json::{from_str, Map, Value};
-use tokio::sync::Mutex;
-
 use crate::{
-    bot::{connection::Connection, RawBot},
-    discord::{token::Token, User},
+    bot::{
+        cache::DEFAULT_MESSAGE_LIMIT,
+        client::{DiscordClient, DEFAULT_API_URL},
+        dispatcher::Dispatcher,
+        events::EVENT_BUFFER,
+        identify_queue::IdentifyQueue,
+        middleware::MiddlewareChain,
+        BlanketImpl, Compression, DispatchMode, Encoding, EventStream, Middleware, RawBot,
+        ReconnectConfig, ShardConfig, ShardId, ShardManager, ShutdownHandle,
+    },
+    discord::{
+        gateway::{DispatchedEvent, GatewayBot, SessionStartLimit},
+        token::Token,
+        Presence, RawApplication, RawUser,
+    },
     prelude::*,
 };
-use std::error::Error;
+use std::{
+    error::Error,
+    fmt::Display,
+    sync::{atomic::AtomicBool, Mutex},
+    time::Duration,
+};
+use tokio::sync::mpsc::{self, Sender};
+use tracing::warn;
 
 #[derive(Debug)]
 pub struct BotTemplate {
     pub(crate) intents: Intents,
     pub(crate) api_version: u8,
+    pub(crate) api_url: Box<str>,
+    pub(crate) gateway_url: Option<Box<str>>,
+    pub(crate) reconnect: ReconnectConfig,
+    pub(crate) shards: ShardConfig,
+    pub(crate) encoding: Encoding,
+    pub(crate) compression: Compression,
+    pub(crate) presence: Option<Presence>,
+    pub(crate) shutdown: ShutdownHandle,
+    pub(crate) shutdown_timeout: Duration,
+    pub(crate) shutdown_on_signal: bool,
+    pub(crate) dispatch_mode: DispatchMode,
+    pub(crate) middleware: MiddlewareChain,
+    pub(crate) message_cache_size: usize,
 }
 
 impl Default for BotTemplate {
@@ -19,6 +49,19 @@ impl Default for BotTemplate {
         Self {
             intents: Intents::all(),
             api_version: 10,
+            api_url: DEFAULT_API_URL.into(),
+            gateway_url: None,
+            reconnect: ReconnectConfig::default(),
+            shards: ShardConfig::default(),
+            encoding: Encoding::default(),
+            compression: Compression::default(),
+            presence: None,
+            shutdown: ShutdownHandle::new(),
+            shutdown_timeout: Duration::from_secs(10),
+            shutdown_on_signal: false,
+            dispatch_mode: DispatchMode::default(),
+            middleware: MiddlewareChain::default(),
+            message_cache_size: DEFAULT_MESSAGE_LIMIT,
         }
     }
 }
@@ -36,6 +79,86 @@ impl BotTemplate {
         self
     }
 
+    #[inline]
+    pub fn api_url(mut self, api_url: impl Into<Box<str>>) -> Self {
+        self.api_url = api_url.into();
+        self
+    }
+
+    #[inline]
+    pub fn gateway_url(mut self, gateway_url: impl Into<Box<str>>) -> Self {
+        self.gateway_url = Some(gateway_url.into());
+        self
+    }
+
+    #[inline]
+    pub fn reconnect(mut self, reconnect: ReconnectConfig) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    #[inline]
+    pub fn shards(mut self, shards: ShardConfig) -> Self {
+        self.shards = shards;
+        self
+    }
+
+    #[inline]
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    #[inline]
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    #[inline]
+    pub fn presence(mut self, presence: Presence) -> Self {
+        self.presence = Some(presence);
+        self
+    }
+
+    #[inline]
+    pub fn shutdown_handle(mut self, shutdown: ShutdownHandle) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    #[inline]
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    #[inline]
+    pub fn shutdown_on_signal(mut self) -> Self {
+        self.shutdown_on_signal = true;
+        self
+    }
+
+    #[inline]
+    pub fn dispatch_mode(mut self, mode: DispatchMode) -> Self {
+        self.dispatch_mode = mode;
+        self
+    }
+
+    // Middleware runs in the order it was added.
+    #[inline]
+    pub fn middleware(mut self, middleware: impl Middleware) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+
+    // Recent messages kept per channel, zero turns message caching off.
+    #[inline]
+    pub fn message_cache_size(mut self, size: usize) -> Self {
+        self.message_cache_size = size;
+        self
+    }
+
     #[inline]
     pub async fn implement_default<Impl>(self, token: Token) -> Result<(), Box<dyn Error>>
     where
@@ -52,6 +175,33 @@ impl BotTemplate {
     where
         Impl: BotImpl + Send + Sync,
     {
+        let (bot, shards) = self.build(implementation, token, None).await?;
+        ShardManager::new(bot, shards)
+            .run()
+            .await
+            .map_err(|err| err as Box<dyn Error>)
+    }
+
+    pub async fn connect(
+        self,
+        token: Token,
+    ) -> Result<(Bot<BlanketImpl>, EventStream), Box<dyn Error>> {
+        let (sender, events) = mpsc::channel(EVENT_BUFFER);
+        let (bot, shards) = self.build(BlanketImpl, token, Some(sender)).await?;
+        let (mut bots, running) = ShardManager::new(bot, shards).start();
+        if bots.is_empty() {
+            return Err("No shard to connect".into());
+        }
+        let shards = tokio::spawn(running.join());
+        Ok((bots.swap_remove(0), EventStream::new(events, shards)))
+    }
+
+    async fn build<Impl>(
+        self,
+        implementation: Impl,
+        token: Token,
+        events: Option<Sender<(ShardId, DispatchedEvent)>>,
+    ) -> Result<(RawBot<Impl>, Vec<ShardId>), Box<dyn Error>> {
         use reqwest::header;
         let new_token = token.into_inner();
         let auth_token = {
@@ -71,48 +221,178 @@ impl BotTemplate {
             map
         };
 
-        let client = bot::client::DiscordClient::from_raw(
+        let client = DiscordClient::with_api_url(
             reqwest::ClientBuilder::default()
                 .default_headers(map)
                 .user_agent("")
                 .build()?,
+            self.api_url,
             self.api_version,
         );
 
-        let gateway = client.get("/gateway/bot").send().await?.text().await?;
-        let map = from_str::<Map<String, Value>>(&gateway)?;
-        let url = {
-            let mut url = map
-                .get("url")
-                .expect("should have url")
-                .as_str()
-                .expect("should be str")
-                .to_string();
-            url.push_str(&format!("/?v={}&encoding=json", self.api_version));
-            url
+        // A forced gateway, such as a proxy, may not come with /gateway/bot.
+        // Without it one shard and a single identify bucket are assumed.
+        let gateway = match (
+            client.fetch::<GatewayBot>("/gateway/bot").await,
+            &self.gateway_url,
+        ) {
+            (Ok(gateway), _) => gateway,
+            (Err(err), Some(url)) => {
+                warn!(error = %err, "Cannot fetch /gateway/bot, using defaults for the forced gateway");
+                GatewayBot {
+                    url: url.clone(),
+                    shards: 1,
+                    session_start_limit: SessionStartLimit {
+                        total: 1000,
+                        remaining: 1000,
+                        reset_after: 0,
+                        max_concurrency: 1,
+                    },
+                }
+            }
+            (Err(err), None) => return Err(err.into()),
         };
-        let raw_user_str = client
-            .get("/users/@me")
-            .send()
-            .await
-            .expect("should succeed")
-            .text()
-            .await
-            .expect("should succeed");
-        let raw_user = serde_json::from_str(&raw_user_str).expect("should be valid user");
-        let me = User::from_raw(raw_user, client.clone());
+        client
+            .raw_cache()
+            .set_message_limit(self.message_cache_size);
+        let me: RawUser = client.fetch("/users/@me").await?;
+        client.raw_cache().set_me(me.clone());
 
-        let connection = Connection::new(&url).await?;
-        let bot = Bot::from_raw(RawBot::<Impl> {
+        let application = client
+            .fetch::<RawApplication>("/applications/@me")
+            .await?
+            .to_mature();
+        // The limited flags grant their intent just like the full ones.
+        let allowed = application.flags().allowed_intents();
+        let disallowed = self.intents - allowed;
+        if !disallowed.is_empty() {
+            return Err(DisallowedIntents(disallowed).into());
+        }
+
+        let bot = RawBot::<Impl> {
             client,
+            me,
             token: new_token,
             state: implementation,
             intents: self.intents,
-            connection,
-            bot: me,
-            last_sequence_number: Mutex::new(None),
-        });
-        bot.run().await?;
+            gateway_url_forced: self.gateway_url.is_some(),
+            gateway_url: self.gateway_url.unwrap_or(gateway.url),
+            reconnect: self.reconnect,
+            encoding: self.encoding,
+            compression: self.compression,
+            identify_queue: IdentifyQueue::new(gateway.session_start_limit),
+            presence: Mutex::new(self.presence),
+            running_shards: Default::default(),
+            members: Default::default(),
+            events,
+            shutdown: self.shutdown,
+            shutdown_timeout: self.shutdown_timeout,
+            dispatcher: Dispatcher::new(self.dispatch_mode),
+            middleware: self.middleware,
+            content_missing: AtomicBool::new(
+                !(self.intents & allowed).contains(Intents::MESSAGE_CONTENT),
+            ),
+        };
+        if self.shutdown_on_signal {
+            bot.shutdown.listen_for_signals();
+        }
+        Ok((bot, self.shards.resolve(gateway.shards)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisallowedIntents(Intents);
+
+impl DisallowedIntents {
+    #[inline]
+    pub fn intents(&self) -> Intents {
+        self.0
+    }
+}
+
+impl Display for DisallowedIntents {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Privileged intents {:?} are not enabled for this application, \
+             enable them in the developer portal or remove them from the template",
+            self.0
+        )
+    }
+}
+
+impl Error for DisallowedIntents {}
+
+#[cfg(test)]
+mod test {
+    use std::{error::Error, sync::atomic::Ordering};
+
+    use futures::StreamExt;
+    use serde_json::json;
+
+    use super::DisallowedIntents;
+    use crate::{
+        bot::BlanketImpl, discord::gateway::DispatchedEvent, prelude::*, testing::MockDiscord,
+    };
+
+    #[tokio::test]
+    async fn forced_gateway_without_gateway_bot() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        mock.rest.route(
+            "GET",
+            "/gateway/bot",
+            404,
+            json!({ "message": "404: Not Found" }),
+        );
+        let (_, mut events) = mock
+            .template()
+            .intents(Intents::GUILDS)
+            .connect(mock.token())
+            .await?;
+        match events.next().await.expect("Should receive ready") {
+            (_, DispatchedEvent::Ready(_)) => {}
+            (_, event) => panic!("Unexpected event: {:?}", event),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn limited_message_content() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        mock.rest.route(
+            "GET",
+            "/applications/@me",
+            200,
+            json!({ "id": "1000", "name": "mili", "flags": 1 << 19 }),
+        );
+        let (bot, _) = mock
+            .template()
+            .intents(Intents::GUILD_MESSAGES | Intents::MESSAGE_CONTENT)
+            .build(BlanketImpl, mock.token(), None)
+            .await?;
+        assert!(!bot.content_missing.load(Ordering::Relaxed));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disallowed_intents() -> Result<(), Box<dyn Error>> {
+        let mock = MockDiscord::start().await?;
+        mock.rest.route(
+            "GET",
+            "/applications/@me",
+            200,
+            json!({ "id": "1000", "name": "mili", "flags": 1 << 14 }),
+        );
+        let err = mock
+            .template()
+            .intents(Intents::GUILDS | Intents::GUILD_MEMBERS | Intents::MESSAGE_CONTENT)
+            .implement(BlanketImpl, mock.token())
+            .await
+            .expect_err("Should refuse to identify");
+        let err = err
+            .downcast_ref::<DisallowedIntents>()
+            .expect("Should report disallowed intents");
+        assert_eq!(err.intents(), Intents::MESSAGE_CONTENT);
         Ok(())
     }
 }
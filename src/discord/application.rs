@@ -0,0 +1,90 @@
+use bitflags::bitflags;
+
+use crate::prelude::*;
+
+bitflags! {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct ApplicationFlags : u64 {
+      const GATEWAY_PRESENCE = 1 << 12;
+      const GATEWAY_PRESENCE_LIMITED = 1 << 13;
+      const GATEWAY_GUILD_MEMBERS = 1 << 14;
+      const GATEWAY_GUILD_MEMBERS_LIMITED = 1 << 15;
+      const GATEWAY_MESSAGE_CONTENT = 1 << 18;
+      const GATEWAY_MESSAGE_CONTENT_LIMITED = 1 << 19;
+    }
+}
+
+impl ApplicationFlags {
+    pub fn allowed_intents(&self) -> Intents {
+        let mut intents = Intents::all() - Intents::privileged();
+        if self.intersects(Self::GATEWAY_PRESENCE | Self::GATEWAY_PRESENCE_LIMITED) {
+            intents |= Intents::GUILD_PRESENCES;
+        }
+        if self.intersects(Self::GATEWAY_GUILD_MEMBERS | Self::GATEWAY_GUILD_MEMBERS_LIMITED) {
+            intents |= Intents::GUILD_MEMBERS;
+        }
+        if self.intersects(Self::GATEWAY_MESSAGE_CONTENT | Self::GATEWAY_MESSAGE_CONTENT_LIMITED) {
+            intents |= Intents::MESSAGE_CONTENT;
+        }
+        intents
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawApplication {
+    id: OwnedID,
+    name: Box<str>,
+    #[serde(default)]
+    flags: u64,
+}
+
+impl RawApplication {
+    #[inline]
+    pub(crate) fn to_mature(self) -> Application {
+        Application {
+            id: self.id,
+            name: self.name,
+            flags: ApplicationFlags::from_bits_retain(self.flags),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Application {
+    id: OwnedID,
+    name: Box<str>,
+    flags: ApplicationFlags,
+}
+
+impl Application {
+    #[inline]
+    pub fn id(&self) -> &ID {
+        &self.id
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn flags(&self) -> ApplicationFlags {
+        self.flags
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ApplicationFlags;
+    use crate::prelude::Intents;
+
+    #[test]
+    fn allowed_intents() {
+        let none = ApplicationFlags::empty().allowed_intents();
+        assert!(!none.intersects(Intents::privileged()));
+        assert!(none.contains(Intents::GUILDS | Intents::GUILD_MESSAGES));
+
+        let limited = ApplicationFlags::GATEWAY_MESSAGE_CONTENT_LIMITED.allowed_intents();
+        assert_eq!(limited & Intents::privileged(), Intents::MESSAGE_CONTENT);
+    }
+}
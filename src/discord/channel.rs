@@ -1,10 +1,12 @@
 use crate::{bot::client::DiscordClient, prelude::*};
 
-use super::SendedMessage;
+use super::{RestError, SendedMessage};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub(crate) struct RawChannel {
-    id: OwnedID,
+    pub(crate) id: OwnedID,
+    #[serde(default)]
+    pub(crate) guild_id: Option<OwnedID>,
     name: Option<Box<str>>,
     #[serde(rename = "nsfw")]
     is_nsfw: Option<bool>,
@@ -21,6 +23,10 @@ impl Channel {
         &self.0.id
     }
 
+    pub fn guild_id(&self) -> Option<&ID> {
+        self.0.guild_id.as_deref()
+    }
+
     pub fn name(&self) -> Option<&str> {
         self.0.name.as_ref().map(Box::as_ref)
     }
@@ -29,16 +35,14 @@ impl Channel {
         self.0.is_nsfw.unwrap_or(false)
     }
 
-    pub async fn send(&self, message: SendedMessage) {
+    pub async fn send(&self, message: SendedMessage) -> Result<(), RestError> {
         let route = format!("/channels/{}/messages", self.id().as_str());
-        let client = self.1.clone();
-
-        client
+        let request = self
+            .1
             .post(&route)
             .header("Content-Type", "application/json")
-            .body(serde_json::to_string(&message).expect("should succeed"))
-            .send()
-            .await
-            .expect("should succeed");
+            .body(serde_json::to_string(&message)?);
+        DiscordClient::execute(request).await?;
+        Ok(())
     }
 }
@@ -0,0 +1,41 @@
+use std::{error::Error, fmt::Display};
+
+#[derive(Debug)]
+pub enum RestError {
+    Request(reqwest::Error),
+    Status { status: u16, body: Box<str> },
+    Deserialize(serde_json::Error),
+}
+
+impl From<reqwest::Error> for RestError {
+    fn from(value: reqwest::Error) -> Self {
+        Self::Request(value)
+    }
+}
+
+impl From<serde_json::Error> for RestError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Deserialize(value)
+    }
+}
+
+impl Display for RestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use RestError::*;
+        match self {
+            Request(err) => write!(f, "Request to Discord failed: {}", err),
+            Status { status, body } => write!(f, "Discord responded with {}: {}", status, body),
+            Deserialize(err) => write!(f, "Cannot deserialize Discord response: {}", err),
+        }
+    }
+}
+
+impl Error for RestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Request(err) => Some(err),
+            Self::Deserialize(err) => Some(err),
+            Self::Status { .. } => None,
+        }
+    }
+}
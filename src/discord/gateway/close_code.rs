@@ -0,0 +1,140 @@
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CloseCode {
+    UnknownError,
+    UnknownOpcode,
+    DecodeError,
+    NotAuthenticated,
+    AuthenticationFailed,
+    AlreadyAuthenticated,
+    InvalidSequence,
+    RateLimited,
+    SessionTimedOut,
+    InvalidShard,
+    ShardingRequired,
+    InvalidApiVersion,
+    InvalidIntents,
+    DisallowedIntents,
+    Other(u16),
+}
+
+impl From<u16> for CloseCode {
+    fn from(value: u16) -> Self {
+        use CloseCode::*;
+        match value {
+            4000 => UnknownError,
+            4001 => UnknownOpcode,
+            4002 => DecodeError,
+            4003 => NotAuthenticated,
+            4004 => AuthenticationFailed,
+            4005 => AlreadyAuthenticated,
+            4007 => InvalidSequence,
+            4008 => RateLimited,
+            4009 => SessionTimedOut,
+            4010 => InvalidShard,
+            4011 => ShardingRequired,
+            4012 => InvalidApiVersion,
+            4013 => InvalidIntents,
+            4014 => DisallowedIntents,
+            code => Other(code),
+        }
+    }
+}
+
+impl From<CloseCode> for u16 {
+    fn from(value: CloseCode) -> Self {
+        value.code()
+    }
+}
+
+impl CloseCode {
+    pub fn code(&self) -> u16 {
+        use CloseCode::*;
+        match self {
+            UnknownError => 4000,
+            UnknownOpcode => 4001,
+            DecodeError => 4002,
+            NotAuthenticated => 4003,
+            AuthenticationFailed => 4004,
+            AlreadyAuthenticated => 4005,
+            InvalidSequence => 4007,
+            RateLimited => 4008,
+            SessionTimedOut => 4009,
+            InvalidShard => 4010,
+            ShardingRequired => 4011,
+            InvalidApiVersion => 4012,
+            InvalidIntents => 4013,
+            DisallowedIntents => 4014,
+            Other(code) => *code,
+        }
+    }
+
+    // Reconnecting after one of these would only get the bot closed again.
+    pub fn is_fatal(&self) -> bool {
+        use CloseCode::*;
+        matches!(
+            self,
+            AuthenticationFailed
+                | InvalidShard
+                | ShardingRequired
+                | InvalidApiVersion
+                | InvalidIntents
+                | DisallowedIntents
+        )
+    }
+
+    #[inline]
+    pub fn can_resume(&self) -> bool {
+        !self.is_fatal() && !matches!(self, Self::InvalidSequence | Self::SessionTimedOut)
+    }
+
+    fn description(&self) -> &'static str {
+        use CloseCode::*;
+        match self {
+            UnknownError => "unknown error",
+            UnknownOpcode => "unknown opcode",
+            DecodeError => "decode error",
+            NotAuthenticated => "not authenticated",
+            AuthenticationFailed => "authentication failed",
+            AlreadyAuthenticated => "already authenticated",
+            InvalidSequence => "invalid sequence",
+            RateLimited => "rate limited",
+            SessionTimedOut => "session timed out",
+            InvalidShard => "invalid shard",
+            ShardingRequired => "sharding required",
+            InvalidApiVersion => "invalid API version",
+            InvalidIntents => "invalid intents",
+            DisallowedIntents => "disallowed intents",
+            Other(_) => "unrecognized close code",
+        }
+    }
+}
+
+impl Display for CloseCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.code(), self.description())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CloseCode;
+
+    #[test]
+    fn classification() {
+        for code in 4000..=4014 {
+            let close_code = CloseCode::from(code);
+            assert_eq!(close_code.code(), code);
+            assert_eq!(
+                close_code.is_fatal(),
+                matches!(code, 4004 | 4010..=4014),
+                "{}",
+                close_code
+            );
+        }
+        assert!(!CloseCode::from(4009).can_resume());
+        assert!(CloseCode::from(4000).can_resume());
+        assert_eq!(CloseCode::from(1001), CloseCode::Other(1001));
+    }
+}
@@ -1,48 +1,70 @@
-use std::error::Error;
-
-use serde_json::{from_value, Value};
+use serde_json::Value;
 
 use crate::{
     bot::client::DiscordClient,
-    discord::{Message, RawMessage},
+    discord::{Member, Message, RawMember, RawMessage},
     prelude::*,
 };
 
 macro_rules! event_from_raw {
     ($data:ident, $event_type:ident) => {{
-        $event_type(from_value($data)?)
+        $event_type(Deserialize::deserialize($data)?)
     }};
     ($data:ident, $event_type:ident, $client:ident, $raw_type:ty) => {{
-        $event_type(from_value::<$raw_type>($data)?.to_mature($client).into())
+        $event_type(<$raw_type>::deserialize($data)?.to_mature($client).into())
     }};
 }
 
 #[derive(Debug)]
 pub enum DispatchedEvent {
     Ready(ReadyEvent),
+    Resumed,
     MessageCreated(MessageCreatedEvent),
+    GuildMembersChunk(GuildMembersChunkEvent),
     Unknown { event_name: Box<str>, data: Value },
 }
 
 impl DispatchedEvent {
     pub fn from_raw(
-        event_name: Box<str>,
+        event_name: &str,
         data: Value,
         client: DiscordClient,
-    ) -> Result<Self, Box<dyn Error>> {
+    ) -> Result<Self, serde_json::Error> {
+        Ok(match Self::from_known(event_name, &data, client)? {
+            Some(event) => event,
+            None => Self::Unknown {
+                event_name: event_name.into(),
+                data,
+            },
+        })
+    }
+
+    // Returns `None` for events without a model, so their payload can be
+    // moved into `Unknown` instead of being copied.
+    pub(crate) fn from_known(
+        event_name: &str,
+        data: &Value,
+        client: DiscordClient,
+    ) -> Result<Option<Self>, serde_json::Error> {
         use DispatchedEvent::*;
-        Ok(match event_name.as_ref() {
+        Ok(Some(match event_name {
             "READY" => event_from_raw!(data, Ready),
+            "RESUMED" => Resumed,
             "MESSAGE_CREATE" => event_from_raw!(data, MessageCreated, client, RawMessage),
-            _ => Unknown { event_name, data },
-        })
+            "GUILD_MEMBERS_CHUNK" => {
+                event_from_raw!(data, GuildMembersChunk, client, RawGuildMembersChunk)
+            }
+            _ => return Ok(None),
+        }))
     }
 
     pub fn name(&self) -> &str {
         use DispatchedEvent::*;
         match self {
             Ready(_) => "READY",
+            Resumed => "RESUMED",
             MessageCreated(_) => "MESSAGE_CREATE",
+            GuildMembersChunk(_) => "GUILD_MEMBERS_CHUNK",
             Unknown { event_name, .. } => &event_name,
         }
     }
@@ -64,3 +86,42 @@ impl From<Message> for MessageCreatedEvent {
 pub struct MessageCreatedEvent {
     pub message: Message,
 }
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RawGuildMembersChunk {
+    guild_id: OwnedID,
+    members: Vec<RawMember>,
+    chunk_index: u32,
+    chunk_count: u32,
+    #[serde(default)]
+    not_found: Vec<OwnedID>,
+    nonce: Option<Box<str>>,
+}
+
+impl RawGuildMembersChunk {
+    #[inline]
+    pub(crate) fn to_mature(self, client: DiscordClient) -> GuildMembersChunkEvent {
+        GuildMembersChunkEvent {
+            guild_id: self.guild_id,
+            members: self
+                .members
+                .into_iter()
+                .map(|member| member.to_mature(client.clone()))
+                .collect(),
+            chunk_index: self.chunk_index,
+            chunk_count: self.chunk_count,
+            not_found: self.not_found,
+            nonce: self.nonce,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GuildMembersChunkEvent {
+    pub guild_id: OwnedID,
+    pub members: Vec<Member>,
+    pub chunk_index: u32,
+    pub chunk_count: u32,
+    pub not_found: Vec<OwnedID>,
+    pub nonce: Option<Box<str>>,
+}
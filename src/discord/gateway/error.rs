@@ -0,0 +1,62 @@
+use std::{error::Error, fmt::Display};
+
+use super::{CloseCode, RawEvent};
+
+#[derive(Debug)]
+pub enum GatewayError {
+    MissingSequenceNumber(RawEvent),
+    MissingEventName(RawEvent),
+    MalformedHello(RawEvent),
+    UnknownOpcode(RawEvent),
+    UnexpectedEvent(RawEvent),
+    Dispatch {
+        raw: RawEvent,
+        source: serde_json::Error,
+    },
+    Closed(CloseCode),
+}
+
+impl GatewayError {
+    pub fn raw_event(&self) -> Option<&RawEvent> {
+        use GatewayError::*;
+        match self {
+            MissingSequenceNumber(raw)
+            | MissingEventName(raw)
+            | MalformedHello(raw)
+            | UnknownOpcode(raw)
+            | UnexpectedEvent(raw)
+            | Dispatch { raw, .. } => Some(raw),
+            Closed(_) => None,
+        }
+    }
+}
+
+impl Display for GatewayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use GatewayError::*;
+        match self {
+            MissingSequenceNumber(raw) => write!(f, "Missing sequence number in {:?}", raw),
+            MissingEventName(raw) => write!(f, "Missing event name in {:?}", raw),
+            MalformedHello(raw) => write!(f, "Malformed hello payload {:?}", raw),
+            UnknownOpcode(raw) => write!(f, "Unknown opcode {} in {:?}", raw.opcode(), raw),
+            UnexpectedEvent(raw) => write!(f, "Unexpected event {:?}", raw),
+            Dispatch { raw, source } => {
+                write!(
+                    f,
+                    "Cannot deserialize dispatched event {:?}: {}",
+                    raw, source
+                )
+            }
+            Closed(code) => write!(f, "Gateway closed the connection with {}", code),
+        }
+    }
+}
+
+impl Error for GatewayError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Dispatch { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
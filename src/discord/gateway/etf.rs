@@ -0,0 +1,379 @@
+use std::{error::Error, fmt::Display, io::Read};
+
+use flate2::read::ZlibDecoder;
+use serde_json::{Map, Number, Value};
+
+const VERSION: u8 = 131;
+
+const NEW_FLOAT_EXT: u8 = 70;
+const COMPRESSED: u8 = 80;
+const SMALL_INTEGER_EXT: u8 = 97;
+const INTEGER_EXT: u8 = 98;
+const FLOAT_EXT: u8 = 99;
+const ATOM_EXT: u8 = 100;
+const SMALL_TUPLE_EXT: u8 = 104;
+const LARGE_TUPLE_EXT: u8 = 105;
+const NIL_EXT: u8 = 106;
+const STRING_EXT: u8 = 107;
+const LIST_EXT: u8 = 108;
+const BINARY_EXT: u8 = 109;
+const SMALL_BIG_EXT: u8 = 110;
+const LARGE_BIG_EXT: u8 = 111;
+const SMALL_ATOM_EXT: u8 = 115;
+const MAP_EXT: u8 = 116;
+const ATOM_UTF8_EXT: u8 = 118;
+const SMALL_ATOM_UTF8_EXT: u8 = 119;
+
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EtfError {
+    UnexpectedEnd,
+    InvalidVersion(u8),
+    UnsupportedTag(u8),
+    InvalidUtf8,
+    InvalidFloat,
+    InvalidCompressed,
+}
+
+impl Display for EtfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use EtfError::*;
+        match self {
+            UnexpectedEnd => f.write_str("Unexpected end of ETF term"),
+            InvalidVersion(version) => write!(f, "Invalid ETF version {}", version),
+            UnsupportedTag(tag) => write!(f, "Unsupported ETF tag {}", tag),
+            InvalidUtf8 => f.write_str("Invalid UTF-8 in ETF term"),
+            InvalidFloat => f.write_str("Invalid float in ETF term"),
+            InvalidCompressed => f.write_str("Invalid compressed ETF term"),
+        }
+    }
+}
+
+impl Error for EtfError {}
+
+pub fn decode(data: &[u8]) -> Result<Value, EtfError> {
+    let mut decoder = Decoder { data };
+    match decoder.u8()? {
+        VERSION => decoder.term(),
+        version => Err(EtfError::InvalidVersion(version)),
+    }
+}
+
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut data = vec![VERSION];
+    encode_term(value, &mut data);
+    data
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], EtfError> {
+        if self.data.len() < len {
+            return Err(EtfError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, EtfError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, EtfError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, EtfError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn str(&mut self, len: usize) -> Result<&'a str, EtfError> {
+        std::str::from_utf8(self.take(len)?).or(Err(EtfError::InvalidUtf8))
+    }
+
+    fn term(&mut self) -> Result<Value, EtfError> {
+        match self.u8()? {
+            NEW_FLOAT_EXT => {
+                let bytes = self.take(8)?.try_into().expect("Should be 8 bytes");
+                float(f64::from_be_bytes(bytes))
+            }
+            FLOAT_EXT => {
+                let text = self.str(31)?.trim_end_matches('\0');
+                float(text.trim().parse().or(Err(EtfError::InvalidFloat))?)
+            }
+            SMALL_INTEGER_EXT => Ok(self.u8()?.into()),
+            INTEGER_EXT => Ok((self.u32()? as i32).into()),
+            SMALL_BIG_EXT => {
+                let len = self.u8()? as usize;
+                self.big(len)
+            }
+            LARGE_BIG_EXT => {
+                let len = self.u32()? as usize;
+                self.big(len)
+            }
+            ATOM_EXT | ATOM_UTF8_EXT => {
+                let len = self.u16()? as usize;
+                self.atom(len)
+            }
+            SMALL_ATOM_EXT | SMALL_ATOM_UTF8_EXT => {
+                let len = self.u8()? as usize;
+                self.atom(len)
+            }
+            BINARY_EXT => {
+                let len = self.u32()? as usize;
+                Ok(self.str(len)?.into())
+            }
+            // Erlang strings are lists of bytes, it is how short integer lists
+            // like `[0, 1]` come over the wire.
+            STRING_EXT => {
+                let len = self.u16()? as usize;
+                let bytes = self.take(len)?;
+                Ok(Value::Array(
+                    bytes.iter().map(|byte| (*byte).into()).collect(),
+                ))
+            }
+            NIL_EXT => Ok(Value::Array(Vec::new())),
+            LIST_EXT => {
+                let len = self.u32()? as usize;
+                let list = self.terms(len)?;
+                // Proper lists end with an empty list as their tail.
+                self.term()?;
+                Ok(Value::Array(list))
+            }
+            SMALL_TUPLE_EXT => {
+                let len = self.u8()? as usize;
+                Ok(Value::Array(self.terms(len)?))
+            }
+            LARGE_TUPLE_EXT => {
+                let len = self.u32()? as usize;
+                Ok(Value::Array(self.terms(len)?))
+            }
+            MAP_EXT => {
+                let len = self.u32()? as usize;
+                let mut map = Map::new();
+                for _ in 0..len {
+                    let key = match self.term()? {
+                        Value::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    map.insert(key, self.term()?);
+                }
+                Ok(Value::Object(map))
+            }
+            // The uncompressed size is untrusted, so it only bounds how much is
+            // inflated instead of being allocated up front.
+            COMPRESSED => {
+                let len = self.u32()? as usize;
+                let mut inflated = Vec::new();
+                ZlibDecoder::new(self.data)
+                    .take(len as u64)
+                    .read_to_end(&mut inflated)
+                    .or(Err(EtfError::InvalidCompressed))?;
+                if inflated.len() != len {
+                    return Err(EtfError::InvalidCompressed);
+                }
+                self.data = &[];
+                Decoder { data: &inflated }.term()
+            }
+            tag => Err(EtfError::UnsupportedTag(tag)),
+        }
+    }
+
+    fn terms(&mut self, len: usize) -> Result<Vec<Value>, EtfError> {
+        (0..len).map(|_| self.term()).collect()
+    }
+
+    fn atom(&mut self, len: usize) -> Result<Value, EtfError> {
+        Ok(match self.str(len)? {
+            "nil" | "null" => Value::Null,
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            atom => atom.into(),
+        })
+    }
+
+    // Snowflakes arrive as big integers, while JSON carries them as strings.
+    // Anything a double cannot hold exactly becomes a decimal string the same
+    // way, so payloads look alike under both encodings.
+    fn big(&mut self, len: usize) -> Result<Value, EtfError> {
+        let negative = self.u8()? != 0;
+        let digits = self.take(len)?;
+        if len > 8 {
+            return Ok(big_to_string(digits, negative).into());
+        }
+        let magnitude = digits
+            .iter()
+            .rev()
+            .fold(0u64, |acc, digit| (acc << 8) | *digit as u64);
+        if magnitude > MAX_SAFE_INTEGER {
+            Ok(big_to_string(digits, negative).into())
+        } else if negative {
+            Ok((magnitude as i64).wrapping_neg().into())
+        } else {
+            Ok(magnitude.into())
+        }
+    }
+}
+
+fn float(float: f64) -> Result<Value, EtfError> {
+    Number::from_f64(float)
+        .map(Value::Number)
+        .ok_or(EtfError::InvalidFloat)
+}
+
+fn big_to_string(digits: &[u8], negative: bool) -> String {
+    let mut magnitude = digits.to_vec();
+    let mut decimal = Vec::new();
+    while magnitude.iter().any(|digit| *digit != 0) {
+        let mut remainder = 0u32;
+        for digit in magnitude.iter_mut().rev() {
+            let current = (remainder << 8) | *digit as u32;
+            *digit = (current / 10) as u8;
+            remainder = current % 10;
+        }
+        decimal.push(b'0' + remainder as u8);
+    }
+    if decimal.is_empty() {
+        decimal.push(b'0');
+    }
+    if negative {
+        decimal.push(b'-');
+    }
+    decimal.reverse();
+    String::from_utf8(decimal).expect("Should be ASCII digits")
+}
+
+fn encode_term(value: &Value, data: &mut Vec<u8>) {
+    match value {
+        Value::Null => encode_atom("nil", data),
+        Value::Bool(boolean) => encode_atom(if *boolean { "true" } else { "false" }, data),
+        Value::Number(number) => {
+            if let Some(int) = number.as_u64() {
+                encode_unsigned(int, false, data);
+            } else if let Some(int) = number.as_i64() {
+                encode_unsigned(int.unsigned_abs(), true, data);
+            } else {
+                data.push(NEW_FLOAT_EXT);
+                let float = number.as_f64().expect("Should be a float");
+                data.extend_from_slice(&float.to_be_bytes());
+            }
+        }
+        Value::String(string) => {
+            data.push(BINARY_EXT);
+            data.extend_from_slice(&(string.len() as u32).to_be_bytes());
+            data.extend_from_slice(string.as_bytes());
+        }
+        Value::Array(list) => {
+            if !list.is_empty() {
+                data.push(LIST_EXT);
+                data.extend_from_slice(&(list.len() as u32).to_be_bytes());
+                list.iter().for_each(|term| encode_term(term, data));
+            }
+            data.push(NIL_EXT);
+        }
+        Value::Object(map) => {
+            data.push(MAP_EXT);
+            data.extend_from_slice(&(map.len() as u32).to_be_bytes());
+            for (key, value) in map {
+                encode_term(&Value::String(key.clone()), data);
+                encode_term(value, data);
+            }
+        }
+    }
+}
+
+fn encode_atom(atom: &str, data: &mut Vec<u8>) {
+    data.push(SMALL_ATOM_UTF8_EXT);
+    data.push(atom.len() as u8);
+    data.extend_from_slice(atom.as_bytes());
+}
+
+fn encode_unsigned(int: u64, negative: bool, data: &mut Vec<u8>) {
+    if !negative && int <= u8::MAX as u64 {
+        data.push(SMALL_INTEGER_EXT);
+        data.push(int as u8);
+    } else if (!negative && int <= i32::MAX as u64) || (negative && int <= 1 << 31) {
+        data.push(INTEGER_EXT);
+        let int = if negative {
+            (int as i64).wrapping_neg() as i32
+        } else {
+            int as i32
+        };
+        data.extend_from_slice(&int.to_be_bytes());
+    } else {
+        let digits = int.to_le_bytes();
+        let len = 8 - int.leading_zeros() as usize / 8;
+        data.push(SMALL_BIG_EXT);
+        data.push(len as u8);
+        data.push(negative as u8);
+        data.extend_from_slice(&digits[..len]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use flate2::{write::ZlibEncoder, Compression};
+    use serde_json::json;
+
+    use super::{decode, encode, EtfError};
+
+    #[test]
+    fn round_trip() {
+        let value = json!({
+            "op": 2,
+            "s": null,
+            "d": {
+                "token": "token",
+                "intents": 33281,
+                "large": -70000,
+                "ratio": 0.5,
+                "compress": false,
+                "shard": [0, 1],
+                "guilds": [],
+                "since": 1700000000000u64,
+            }
+        });
+        assert_eq!(decode(&encode(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn snowflake() {
+        // 1196020343484190790 as SMALL_BIG_EXT
+        let data = [131, 110, 8, 0, 70, 16, 132, 101, 42, 30, 153, 16];
+        assert_eq!(decode(&data).unwrap(), json!("1196020343484190790"));
+    }
+
+    #[test]
+    fn string_ext() {
+        let data = [131, 107, 0, 2, 0, 1];
+        assert_eq!(decode(&data).unwrap(), json!([0, 1]));
+    }
+
+    #[test]
+    fn compressed_size() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[97, 42]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let with_len = |len: u32| {
+            let mut data = vec![131, 80];
+            data.extend_from_slice(&len.to_be_bytes());
+            data.extend_from_slice(&compressed);
+            data
+        };
+
+        assert_eq!(decode(&with_len(2)).unwrap(), json!(42));
+        assert_eq!(
+            decode(&with_len(u32::MAX)),
+            Err(EtfError::InvalidCompressed)
+        );
+    }
+}
@@ -1,11 +1,14 @@
 use std::time::Duration;
 
 use serde_json::Value;
-use tokio::time::Interval;
 
-use crate::{bot::client::DiscordClient, discord::gateway::DispatchedEvent, prelude::*};
+use crate::{
+    bot::client::DiscordClient,
+    discord::gateway::{DispatchedEvent, GatewayError},
+    prelude::*,
+};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RawEvent {
     #[serde(rename = "op")]
     opcode: u8,
@@ -13,7 +16,7 @@ pub struct RawEvent {
     event_name: Option<Box<str>>,
     #[serde(rename = "s")]
     sequence_number: Option<usize>,
-    #[serde(rename = "d")]
+    #[serde(rename = "d", default)]
     data: Value,
 }
 
@@ -31,12 +34,20 @@ impl RawEvent {
         self.opcode
     }
 
+    pub fn event_name(&self) -> Option<&str> {
+        self.event_name.as_deref()
+    }
+
+    pub fn sequence_number(&self) -> Option<usize> {
+        self.sequence_number
+    }
+
     pub fn data(&self) -> &Value {
         &self.data
     }
 
     #[inline]
-    pub fn try_into_mature(self, client: DiscordClient) -> Result<Event, ()> {
+    pub fn try_into_mature(self, client: DiscordClient) -> Result<Event, GatewayError> {
         Event::from_raw(self, client)
     }
 }
@@ -48,9 +59,13 @@ pub enum Event {
         event: DispatchedEvent,
     },
     Hello {
-        heartbeat: Interval,
+        heartbeat_interval: Duration,
     },
+    HeartbeatRequest,
     Reconnect,
+    InvalidSession {
+        resumable: bool,
+    },
     HeartbeatACK,
 }
 
@@ -65,45 +80,51 @@ impl Event {
 }
 
 impl TryFrom<(RawEvent, DiscordClient)> for Event {
-    type Error = ();
+    type Error = GatewayError;
 
     fn try_from(value: (RawEvent, DiscordClient)) -> Result<Self, Self::Error> {
         use Event::*;
 
-        match value.0.opcode() {
+        let (raw, client) = value;
+        match raw.opcode() {
             0 => {
-                let RawEvent {
-                    sequence_number,
-                    data,
-                    event_name,
-                    ..
-                } = value.0;
-                let seq_num = sequence_number.ok_or(())?; // TODO: Change to error
-                let name = event_name.ok_or(())?; // TODO: Change to error
-                let dispatch = DispatchedEvent::from_raw(name, data, value.1).or(Err(()))?; // TODO: Change to error
-                Ok(Dispatch {
-                    sequence_number: seq_num,
-                    event: dispatch,
-                })
+                let Some(seq_num) = raw.sequence_number() else {
+                    return Err(GatewayError::MissingSequenceNumber(raw));
+                };
+                let Some(name) = raw.event_name() else {
+                    return Err(GatewayError::MissingEventName(raw));
+                };
+                match DispatchedEvent::from_known(name, raw.data(), client) {
+                    Ok(Some(dispatch)) => Ok(Dispatch {
+                        sequence_number: seq_num,
+                        event: dispatch,
+                    }),
+                    Ok(None) => Ok(Dispatch {
+                        sequence_number: seq_num,
+                        event: DispatchedEvent::Unknown {
+                            event_name: raw.event_name.expect("Should have been checked"),
+                            data: raw.data,
+                        },
+                    }),
+                    Err(source) => Err(GatewayError::Dispatch { raw, source }),
+                }
             }
+            1 => Ok(HeartbeatRequest),
             7 => Ok(Reconnect),
+            9 => Ok(InvalidSession {
+                resumable: raw.data().as_bool().unwrap_or(false),
+            }),
             10 => {
-                let heartbeat = value
-                    .0
-                    .data()
-                    .as_object()
-                    .ok_or(())? // TODO: Change to error
-                    .get("heartbeat_interval")
-                    .ok_or(())? // TODO: Change to error
-                    .as_u64()
-                    .ok_or(())?; // TODO: Change to error
-                let interval = tokio::time::interval(Duration::from_millis(heartbeat));
+                let Some(heartbeat) = raw.data().get("heartbeat_interval").and_then(Value::as_u64)
+                else {
+                    return Err(GatewayError::MalformedHello(raw));
+                };
                 Ok(Hello {
-                    heartbeat: interval,
+                    heartbeat_interval: Duration::from_millis(heartbeat),
                 })
             }
             11 => Ok(HeartbeatACK),
-            _ => unimplemented!(), // TODO
+            _ => Err(GatewayError::UnknownOpcode(raw)),
         }
     }
 }
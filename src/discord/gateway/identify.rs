@@ -1,4 +1,8 @@
-use crate::prelude::*;
+use std::fmt::Debug;
+
+use crate::{discord::Presence, prelude::*};
+
+const REDACTED: &str = "<redacted>";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConnectionProperties<'a> {
@@ -7,9 +11,58 @@ pub struct ConnectionProperties<'a> {
     pub device: &'a str,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Serialize)]
 pub struct IdentifyData<'a> {
     pub token: &'a str,
     pub intents: u64,
     pub properties: ConnectionProperties<'a>,
+    pub shard: [u32; 2],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence: Option<Presence>,
+}
+
+impl Debug for IdentifyData<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IdentifyData")
+            .field("token", &REDACTED)
+            .field("intents", &self.intents)
+            .field("properties", &self.properties)
+            .field("shard", &self.shard)
+            .field("presence", &self.presence)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResumeData<'a> {
+    pub token: &'a str,
+    pub session_id: &'a str,
+    pub seq: Option<usize>,
+}
+
+impl Debug for ResumeData<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResumeData")
+            .field("token", &REDACTED)
+            .field("session_id", &self.session_id)
+            .field("seq", &self.seq)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ResumeData;
+    use crate::prelude::Token;
+
+    #[test]
+    fn redacted_token() {
+        let resume = ResumeData {
+            token: "secret-token",
+            session_id: "session",
+            seq: Some(1),
+        };
+        assert!(!format!("{:?}", resume).contains("secret-token"));
+        assert!(!format!("{:?}", Token::insecure("secret-token")).contains("secret-token"));
+    }
 }
@@ -0,0 +1,13 @@
+use crate::prelude::*;
+
+#[derive(Debug, Serialize)]
+pub struct RequestGuildMembersData<'a> {
+    pub guild_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<&'a str>,
+    pub limit: u32,
+    pub presences: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_ids: Option<&'a [OwnedID]>,
+    pub nonce: &'a str,
+}
@@ -1,8 +1,17 @@
+mod close_code;
 mod dispatch;
+mod error;
+pub mod etf;
 mod event;
 mod identify;
+mod members;
 pub(crate) mod recover_data;
+mod session;
 
+pub use close_code::*;
 pub use dispatch::*;
-pub(crate) use event::*;
+pub use error::*;
+pub use event::*;
 pub(crate) use identify::*;
+pub(crate) use members::*;
+pub use session::*;
@@ -0,0 +1,16 @@
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GatewayBot {
+    pub url: Box<str>,
+    pub shards: u32,
+    pub session_start_limit: SessionStartLimit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct SessionStartLimit {
+    pub total: u32,
+    pub remaining: u32,
+    pub reset_after: u64,
+    pub max_concurrency: u32,
+}
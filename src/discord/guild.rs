@@ -0,0 +1,73 @@
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawGuild {
+    pub(crate) id: OwnedID,
+    pub(crate) name: Box<str>,
+    pub(crate) owner_id: Option<OwnedID>,
+    pub(crate) icon: Option<Box<str>>,
+    #[serde(default)]
+    pub(crate) unavailable: bool,
+}
+
+impl RawGuild {
+    // Stands in for a guild Discord has only sent the id of so far.
+    #[inline]
+    pub(crate) fn unavailable(id: OwnedID) -> Self {
+        Self {
+            id,
+            name: "".into(),
+            owner_id: None,
+            icon: None,
+            unavailable: true,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn to_mature(self) -> Guild {
+        Guild {
+            id: self.id,
+            name: self.name,
+            owner_id: self.owner_id,
+            icon: self.icon,
+            unavailable: self.unavailable,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Guild {
+    id: OwnedID,
+    name: Box<str>,
+    owner_id: Option<OwnedID>,
+    icon: Option<Box<str>>,
+    unavailable: bool,
+}
+
+impl Guild {
+    #[inline]
+    pub fn id(&self) -> &ID {
+        &self.id
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn owner_id(&self) -> Option<&ID> {
+        self.owner_id.as_deref()
+    }
+
+    #[inline]
+    pub fn icon(&self) -> Option<&str> {
+        self.icon.as_deref()
+    }
+
+    // Set while Discord reports the guild as down, cleared by its next GUILD_CREATE.
+    #[inline]
+    pub fn unavailable(&self) -> bool {
+        self.unavailable
+    }
+}
@@ -34,6 +34,11 @@ impl Intents {
     pub fn as_u64(&self) -> u64 {
         self.bits() as u64
     }
+
+    #[inline]
+    pub fn privileged() -> Self {
+        Self::GUILD_MEMBERS | Self::GUILD_PRESENCES | Self::MESSAGE_CONTENT
+    }
 }
 
 impl Default for Intents {
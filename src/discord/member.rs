@@ -0,0 +1,54 @@
+use crate::{bot::client::DiscordClient, prelude::*};
+
+use super::{OwnedID, RawUser, User};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RawMember {
+    pub(crate) user: Option<RawUser>,
+    nick: Option<Box<str>>,
+    #[serde(default)]
+    roles: Vec<OwnedID>,
+    joined_at: Option<Box<str>>,
+}
+
+impl RawMember {
+    #[inline]
+    pub(crate) fn to_mature(self, client: DiscordClient) -> Member {
+        Member {
+            user: self.user.map(|user| User::from_raw(user, client)),
+            nick: self.nick,
+            roles: self.roles,
+            joined_at: self.joined_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    user: Option<User>,
+    nick: Option<Box<str>>,
+    roles: Vec<OwnedID>,
+    joined_at: Option<Box<str>>,
+}
+
+impl Member {
+    #[inline]
+    pub fn user(&self) -> Option<&User> {
+        self.user.as_ref()
+    }
+
+    #[inline]
+    pub fn nick(&self) -> Option<&str> {
+        self.nick.as_deref()
+    }
+
+    #[inline]
+    pub fn roles(&self) -> &[OwnedID] {
+        &self.roles
+    }
+
+    #[inline]
+    pub fn joined_at(&self) -> Option<&str> {
+        self.joined_at.as_deref()
+    }
+}
@@ -2,14 +2,16 @@ use std::fmt::Debug;
 
 use crate::{bot::client::DiscordClient, prelude::*};
 
-use super::{Channel, OwnedID, RawUser, User, ID};
+use super::{Channel, OwnedID, RawChannel, RawUser, RestError, User, ID};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct RawMessage {
-    id: OwnedID,
-    channel_id: OwnedID,
-    author: RawUser,
-    content: String,
+    pub(crate) id: OwnedID,
+    pub(crate) channel_id: OwnedID,
+    #[serde(default)]
+    guild_id: Option<OwnedID>,
+    pub(crate) author: RawUser,
+    pub(crate) content: String,
     tts: bool,
 }
 
@@ -26,6 +28,7 @@ impl RawMessage {
                 Some(self.content)
             },
             channel_id: self.channel_id,
+            guild_id: self.guild_id,
             client,
         }
     }
@@ -35,6 +38,7 @@ impl RawMessage {
 pub struct Message {
     id: OwnedID,
     channel_id: OwnedID,
+    guild_id: Option<OwnedID>,
     author: User,
     content: Option<String>,
     tts: bool,
@@ -52,6 +56,11 @@ impl Message {
         &self.channel_id
     }
 
+    #[inline]
+    pub fn guild_id(&self) -> Option<&ID> {
+        self.guild_id.as_deref()
+    }
+
     #[inline]
     pub fn author(&self) -> &User {
         &self.author
@@ -67,21 +76,14 @@ impl Message {
         self.tts
     }
 
-    pub async fn channel(&self) -> Channel {
+    pub async fn channel(&self) -> Result<Channel, RestError> {
+        if let Some(channel) = self.client.cache().channel(self.channel_id()) {
+            return Ok(channel);
+        }
         let route = format!("/channels/{}", self.channel_id());
-        let raw_channel = serde_json::from_str(
-            &self
-                .client
-                .get(&route)
-                .send()
-                .await
-                .expect("should succeed")
-                .text()
-                .await
-                .expect("should succeed"),
-        )
-        .expect("should be valid channel");
-        Channel::from_raw(raw_channel, self.client.clone())
+        let raw_channel: RawChannel = self.client.fetch(&route).await?;
+        self.client.raw_cache().insert_channel(raw_channel.clone());
+        Ok(Channel::from_raw(raw_channel, self.client.clone()))
     }
 }
 
@@ -2,13 +2,25 @@ pub mod gateway;
 pub mod intents;
 pub mod token;
 
+mod application;
 mod channel;
 mod command;
+mod error;
+mod guild;
+mod member;
 mod message;
+mod presence;
+mod role;
 mod snowflake_id;
 mod user;
+pub use application::*;
 pub use channel::*;
 pub use command::*;
+pub use error::*;
+pub use guild::*;
+pub use member::*;
 pub use message::*;
+pub use presence::*;
+pub use role::*;
 pub use snowflake_id::*;
 pub use user::*;
@@ -0,0 +1,164 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    #[default]
+    Online,
+    Idle,
+    Dnd,
+    Invisible,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(into = "u8")]
+pub enum ActivityType {
+    Playing,
+    Streaming,
+    Listening,
+    Watching,
+    Custom,
+    Competing,
+}
+
+impl ActivityType {
+    pub fn as_u8(&self) -> u8 {
+        use ActivityType::*;
+        match self {
+            Playing => 0,
+            Streaming => 1,
+            Listening => 2,
+            Watching => 3,
+            Custom => 4,
+            Competing => 5,
+        }
+    }
+}
+
+impl From<ActivityType> for u8 {
+    fn from(value: ActivityType) -> Self {
+        value.as_u8()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Activity {
+    name: Box<str>,
+    #[serde(rename = "type")]
+    activity_type: ActivityType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<Box<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<Box<str>>,
+}
+
+impl Activity {
+    fn new(activity_type: ActivityType, name: impl Into<Box<str>>) -> Self {
+        Self {
+            name: name.into(),
+            activity_type,
+            url: None,
+            state: None,
+        }
+    }
+
+    #[inline]
+    pub fn playing(name: impl Into<Box<str>>) -> Self {
+        Self::new(ActivityType::Playing, name)
+    }
+
+    #[inline]
+    pub fn streaming(name: impl Into<Box<str>>, url: impl Into<Box<str>>) -> Self {
+        Self {
+            url: Some(url.into()),
+            ..Self::new(ActivityType::Streaming, name)
+        }
+    }
+
+    #[inline]
+    pub fn listening(name: impl Into<Box<str>>) -> Self {
+        Self::new(ActivityType::Listening, name)
+    }
+
+    #[inline]
+    pub fn watching(name: impl Into<Box<str>>) -> Self {
+        Self::new(ActivityType::Watching, name)
+    }
+
+    #[inline]
+    pub fn custom(state: impl Into<Box<str>>) -> Self {
+        Self {
+            state: Some(state.into()),
+            ..Self::new(ActivityType::Custom, "Custom Status")
+        }
+    }
+
+    #[inline]
+    pub fn competing(name: impl Into<Box<str>>) -> Self {
+        Self::new(ActivityType::Competing, name)
+    }
+
+    #[inline]
+    pub fn state(mut self, state: impl Into<Box<str>>) -> Self {
+        self.state = Some(state.into());
+        self
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn activity_type(&self) -> ActivityType {
+        self.activity_type
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Presence {
+    since: Option<u64>,
+    activities: Vec<Activity>,
+    status: Status,
+    afk: bool,
+}
+
+impl Presence {
+    #[inline]
+    pub fn new(status: Status) -> Self {
+        Self {
+            status,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn activity(mut self, activity: Activity) -> Self {
+        self.activities.push(activity);
+        self
+    }
+
+    // `since` is the unix time in milliseconds at which the client went idle.
+    #[inline]
+    pub fn afk(mut self, afk: bool) -> Self {
+        self.afk = afk;
+        self.since = afk.then(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64)
+        });
+        self
+    }
+
+    #[inline]
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    #[inline]
+    pub fn activities(&self) -> &[Activity] {
+        &self.activities
+    }
+}
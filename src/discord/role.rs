@@ -0,0 +1,69 @@
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawRole {
+    pub(crate) id: OwnedID,
+    name: Box<str>,
+    #[serde(default)]
+    color: u32,
+    #[serde(default)]
+    position: i32,
+    #[serde(default)]
+    permissions: Box<str>,
+}
+
+impl RawRole {
+    #[inline]
+    pub(crate) fn to_mature(self, guild_id: OwnedID) -> Role {
+        Role {
+            id: self.id,
+            guild_id,
+            name: self.name,
+            color: self.color,
+            position: self.position,
+            permissions: self.permissions,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Role {
+    id: OwnedID,
+    guild_id: OwnedID,
+    name: Box<str>,
+    color: u32,
+    position: i32,
+    permissions: Box<str>,
+}
+
+impl Role {
+    #[inline]
+    pub fn id(&self) -> &ID {
+        &self.id
+    }
+
+    #[inline]
+    pub fn guild_id(&self) -> &ID {
+        &self.guild_id
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn color(&self) -> u32 {
+        self.color
+    }
+
+    #[inline]
+    pub fn position(&self) -> i32 {
+        self.position
+    }
+
+    #[inline]
+    pub fn permissions(&self) -> &str {
+        &self.permissions
+    }
+}
@@ -1,13 +1,15 @@
 use std::{borrow::Borrow, fmt::Display, ops::Deref};
 
+use serde::de::{self, Visitor};
+
 use crate::prelude::*;
 
 #[repr(transparent)]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub struct ID(str);
 
 #[repr(transparent)]
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
 pub struct OwnedID(Box<ID>);
 
 impl ID {
@@ -100,3 +102,35 @@ impl Deref for OwnedID {
         self.as_id()
     }
 }
+
+// Snowflakes are strings in JSON payloads but integers in ETF payloads.
+impl<'de> Deserialize<'de> for OwnedID {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct IDVisitor;
+
+        impl<'de> Visitor<'de> for IDVisitor {
+            type Value = OwnedID;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a snowflake as string or integer")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(ID::from_raw(v).to_owned())
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(ID::from_raw(&v.to_string()).to_owned())
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(ID::from_raw(&v.to_string()).to_owned())
+            }
+        }
+
+        deserializer.deserialize_any(IDVisitor)
+    }
+}
@@ -1,4 +1,4 @@
-use std::{env, ffi::OsStr};
+use std::{env, ffi::OsStr, fmt::Debug};
 
 pub struct Token(RawToken);
 
@@ -40,3 +40,10 @@ impl Default for Token {
         Self::ENV
     }
 }
+
+// Tokens must never end up in logs.
+impl Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Token(<redacted>)")
+    }
+}
@@ -2,8 +2,10 @@ use crate::{bot::client::DiscordClient, prelude::*};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct RawUser {
-    id: OwnedID,
+    pub(crate) id: OwnedID,
     username: String,
+    #[serde(default)]
+    bot: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -21,6 +23,10 @@ impl User {
     pub fn username(&self) -> &str {
         &self.0.username
     }
+
+    pub fn is_bot(&self) -> bool {
+        self.0.bot
+    }
 }
 
 impl PartialEq for User {
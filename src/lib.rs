@@ -1,13 +1,24 @@
 pub mod bot;
 pub mod discord;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(not(feature = "metrics"))]
+mod metrics;
 pub mod prelude;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 #[cfg(test)]
 mod test {
 
     use std::error::Error;
 
-    use crate::discord::SendedMessage;
+    use serde_json::json;
+
+    use crate::{
+        discord::{RestError, SendedMessage},
+        testing::MockDiscord,
+    };
 
     use super::prelude::{self, *};
 
@@ -15,31 +26,57 @@ mod test {
     struct MyBot;
 
     impl BotImpl for MyBot {
-        async fn on_message_created(bot: Bot<Self>, msg: discord::gateway::MessageCreatedEvent) {
-            println!("Message created");
-            if msg.message.author() != bot.me() {
+        type Error = RestError;
+
+        async fn on_message_created(
+            bot: Bot<Self>,
+            msg: discord::gateway::MessageCreatedEvent,
+        ) -> Result<(), RestError> {
+            if *msg.message.author() != bot.me() {
                 msg.message
                     .channel()
-                    .await
+                    .await?
                     .send(SendedMessage::plain("hello!").tts(true))
-                    .await;
+                    .await?;
             }
+            Ok(())
         }
     }
 
     #[prelude::test(flavor = "multi_thread", worker_threads = 5)]
     async fn ok_test() -> Result<(), Box<dyn Error>> {
-        BotTemplate::default()
-            .api_version(10)
+        let mock = MockDiscord::start().await?;
+        mock.rest.route(
+            "GET",
+            "/channels/2000",
+            200,
+            json!({ "id": "2000", "name": "general" }),
+        );
+        mock.rest
+            .route("POST", "/channels/2000/messages", 200, json!({}));
+
+        let bot = mock
+            .template()
             .intents(
                 Intents::GUILDS
                     | Intents::MESSAGE_CONTENT
                     | Intents::DIRECT_MESSAGES
                     | Intents::GUILD_MESSAGES,
             )
-            .implement_default::<MyBot>(Token::insecure(
-                "MTE5NjAyMDM0MzQ4NDE5MDc5MA.GM8za1.XpcGIbmmvwzCNfl4JiVdEPlBLSE-YciTSDvb8A",
-            ))
-            .await
+            .implement_default::<MyBot>(mock.token());
+        let request = mock
+            .run_until(bot, async {
+                mock.message_create("4000", "2000", "hi");
+                loop {
+                    let request = mock.rest.next_request().await.expect("Should be running");
+                    if request.method.as_ref() == "POST" {
+                        return request;
+                    }
+                }
+            })
+            .await;
+        assert_eq!(request.path.as_ref(), "/channels/2000/messages");
+        assert_eq!(request.body, json!({ "content": "hello!", "tts": true }));
+        Ok(())
     }
 }
@@ -0,0 +1,284 @@
+use std::time::Duration;
+#[cfg(feature = "metrics")]
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    io,
+    sync::{Mutex, OnceLock},
+};
+
+#[cfg(feature = "metrics")]
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, ToSocketAddrs},
+};
+
+#[cfg(feature = "metrics")]
+const BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+#[cfg(feature = "metrics")]
+type Key = (&'static str, Box<[(&'static str, Box<str>)]>);
+
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+pub struct Registry {
+    counters: Mutex<BTreeMap<Key, u64>>,
+    histograms: Mutex<BTreeMap<Key, Histogram>>,
+}
+
+#[cfg(feature = "metrics")]
+impl Registry {
+    fn key(name: &'static str, labels: &[(&'static str, &str)]) -> Key {
+        let labels = labels
+            .iter()
+            .map(|(label, value)| (*label, (*value).into()))
+            .collect();
+        (name, labels)
+    }
+
+    fn increment(&self, name: &'static str, labels: &[(&'static str, &str)]) {
+        *self
+            .counters
+            .lock()
+            .expect("Should not be poisoned")
+            .entry(Self::key(name, labels))
+            .or_default() += 1;
+    }
+
+    fn observe(&self, name: &'static str, labels: &[(&'static str, &str)], value: Duration) {
+        let value = value.as_secs_f64();
+        let mut histograms = self.histograms.lock().expect("Should not be poisoned");
+        let histogram = histograms.entry(Self::key(name, labels)).or_default();
+        for (bucket, bound) in histogram.buckets.iter_mut().zip(BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        histogram.count += 1;
+        histogram.sum += value;
+    }
+
+    // Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let counters = self
+            .counters
+            .lock()
+            .expect("Should not be poisoned")
+            .clone();
+        let mut last_name = "";
+        for ((name, labels), value) in &counters {
+            if *name != last_name {
+                let _ = writeln!(output, "# TYPE {} counter", name);
+                last_name = name;
+            }
+            let _ = writeln!(output, "{}{} {}", name, render_labels(labels, None), value);
+        }
+
+        let histograms = self
+            .histograms
+            .lock()
+            .expect("Should not be poisoned")
+            .clone();
+        for ((name, labels), histogram) in &histograms {
+            if *name != last_name {
+                let _ = writeln!(output, "# TYPE {} histogram", name);
+                last_name = name;
+            }
+            for (count, bound) in histogram.buckets.iter().zip(BUCKETS) {
+                let le = bound.to_string();
+                let labels = render_labels(labels, Some(&le));
+                let _ = writeln!(output, "{}_bucket{} {}", name, labels, count);
+            }
+            let labels_inf = render_labels(labels, Some("+Inf"));
+            let labels = render_labels(labels, None);
+            let _ = writeln!(output, "{}_bucket{} {}", name, labels_inf, histogram.count);
+            let _ = writeln!(output, "{}_sum{} {}", name, labels, histogram.sum);
+            let _ = writeln!(output, "{}_count{} {}", name, labels, histogram.count);
+        }
+        output
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn render_labels(labels: &[(&'static str, Box<str>)], le: Option<&str>) -> String {
+    let labels = labels
+        .iter()
+        .map(|(label, value)| (*label, value.as_ref()))
+        .chain(le.map(|le| ("le", le)))
+        .map(|(label, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", label, value)
+        })
+        .collect::<Vec<_>>();
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+// Every bot in the process reports into the same registry.
+#[cfg(feature = "metrics")]
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::default)
+}
+
+// Serves the registry over plain HTTP on every path, which is all a
+// Prometheus scraper needs.
+#[cfg(feature = "metrics")]
+pub async fn serve(addr: impl ToSocketAddrs) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buffer).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(read) => request.extend_from_slice(&buffer[..read]),
+                }
+            }
+            let body = registry().render();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\n\
+                 Content-Type: text/plain; version=0.0.4\r\n\
+                 Content-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+// The recorders below compile to nothing without the `metrics` feature.
+
+#[inline]
+pub(crate) fn event_received(event: &str) {
+    #[cfg(feature = "metrics")]
+    registry().increment("mili_gateway_events_total", &[("event", event)]);
+    #[cfg(not(feature = "metrics"))]
+    let _ = event;
+}
+
+#[inline]
+pub(crate) fn heartbeat_latency(shard: u32, latency: Duration) {
+    #[cfg(feature = "metrics")]
+    registry().observe(
+        "mili_gateway_heartbeat_latency_seconds",
+        &[("shard", &shard.to_string())],
+        latency,
+    );
+    #[cfg(not(feature = "metrics"))]
+    let _ = (shard, latency);
+}
+
+#[inline]
+pub(crate) fn reconnect(reason: &str) {
+    #[cfg(feature = "metrics")]
+    registry().increment("mili_gateway_reconnects_total", &[("reason", reason)]);
+    #[cfg(not(feature = "metrics"))]
+    let _ = reason;
+}
+
+#[inline]
+pub(crate) fn rate_limit_wait(waited: Duration) {
+    #[cfg(feature = "metrics")]
+    registry().observe("mili_gateway_rate_limit_wait_seconds", &[], waited);
+    #[cfg(not(feature = "metrics"))]
+    let _ = waited;
+}
+
+#[inline]
+pub(crate) fn rest_request(method: &str, path: &str, status: u16) {
+    #[cfg(feature = "metrics")]
+    {
+        // Snowflakes would give every channel and guild its own series.
+        let route = path
+            .split('/')
+            .map(|segment| {
+                if !segment.is_empty() && segment.bytes().all(|byte| byte.is_ascii_digit()) {
+                    ":id"
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        registry().increment(
+            "mili_rest_requests_total",
+            &[
+                ("method", method),
+                ("route", &route),
+                ("status", &status.to_string()),
+            ],
+        );
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (method, path, status);
+}
+
+#[inline]
+pub(crate) fn handler_finished(event: &str, duration: Duration, failure: Option<&'static str>) {
+    #[cfg(feature = "metrics")]
+    {
+        registry().observe(
+            "mili_handler_duration_seconds",
+            &[("event", event)],
+            duration,
+        );
+        if let Some(kind) = failure {
+            registry().increment(
+                "mili_handler_failures_total",
+                &[("event", event), ("kind", kind)],
+            );
+        }
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (event, duration, failure);
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod test {
+    use std::time::Duration;
+
+    use super::Registry;
+
+    #[test]
+    fn render() {
+        let registry = Registry::default();
+        registry.increment("mili_gateway_events_total", &[("event", "READY")]);
+        registry.increment("mili_gateway_events_total", &[("event", "READY")]);
+        registry.observe(
+            "mili_handler_duration_seconds",
+            &[],
+            Duration::from_millis(20),
+        );
+
+        let rendered = registry.render();
+        assert!(rendered.contains("# TYPE mili_gateway_events_total counter\n"));
+        assert!(rendered.contains("mili_gateway_events_total{event=\"READY\"} 2\n"));
+        assert!(rendered.contains("# TYPE mili_handler_duration_seconds histogram\n"));
+        assert!(rendered.contains("mili_handler_duration_seconds_bucket{le=\"0.01\"} 0\n"));
+        assert!(rendered.contains("mili_handler_duration_seconds_bucket{le=\"0.025\"} 1\n"));
+        assert!(rendered.contains("mili_handler_duration_seconds_bucket{le=\"+Inf\"} 1\n"));
+        assert!(rendered.contains("mili_handler_duration_seconds_count 1\n"));
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_tungstenite::{
+    accept_hdr_async,
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        protocol::{frame::coding::CloseCode, CloseFrame},
+        Message,
+    },
+};
+
+use crate::discord::gateway::etf;
+
+struct GatewayState {
+    url: Box<str>,
+    heartbeat_interval: u64,
+    user: Value,
+    sequence: AtomicUsize,
+    connections: AtomicUsize,
+    sessions: AtomicUsize,
+    session_id: Mutex<Option<String>>,
+    current: Mutex<Option<Client>>,
+    received: mpsc::UnboundedSender<Value>,
+    closed: mpsc::UnboundedSender<Option<u16>>,
+}
+
+// The most recent connection, spoken to in the encoding it asked for.
+#[derive(Clone)]
+struct Client {
+    sender: mpsc::UnboundedSender<Message>,
+    etf: bool,
+}
+
+impl Client {
+    fn send(&self, payload: &Value) -> bool {
+        let msg = match self.etf {
+            true => Message::Binary(etf::encode(payload)),
+            false => Message::Text(payload.to_string()),
+        };
+        self.sender.send(msg).is_ok()
+    }
+
+    fn decode(&self, msg: &Message) -> Option<Value> {
+        match msg {
+            Message::Binary(data) if self.etf => etf::decode(data).ok(),
+            Message::Text(text) if !self.etf => serde_json::from_str(text).ok(),
+            _ => None,
+        }
+    }
+}
+
+impl GatewayState {
+    fn send(&self, payload: Value) -> bool {
+        let current = self.current.lock().expect("Should not be poisoned");
+        current.as_ref().is_some_and(|client| client.send(&payload))
+    }
+
+    fn dispatch(&self, name: &str, data: Value) -> bool {
+        let seq = self.sequence.fetch_add(1, Ordering::SeqCst) + 1;
+        self.send(json!({ "op": 0, "t": name, "s": seq, "d": data }))
+    }
+}
+
+pub struct MockGateway {
+    state: Arc<GatewayState>,
+    received: tokio::sync::Mutex<mpsc::UnboundedReceiver<Value>>,
+    closed: tokio::sync::Mutex<mpsc::UnboundedReceiver<Option<u16>>>,
+}
+
+impl MockGateway {
+    pub async fn start(user: Value, heartbeat_interval: u64) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (sender, recver) = mpsc::unbounded_channel();
+        let (closed_sender, closed) = mpsc::unbounded_channel();
+        let state = Arc::new(GatewayState {
+            url: format!("ws://{}", addr).into(),
+            heartbeat_interval,
+            user,
+            sequence: AtomicUsize::new(0),
+            connections: AtomicUsize::new(0),
+            sessions: AtomicUsize::new(0),
+            session_id: Mutex::new(None),
+            current: Mutex::new(None),
+            received: sender,
+            closed: closed_sender,
+        });
+        tokio::spawn(accept_loop(listener, state.clone()));
+        Ok(Self {
+            state,
+            received: tokio::sync::Mutex::new(recver),
+            closed: tokio::sync::Mutex::new(closed),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.state.url
+    }
+
+    // Sockets opened by any shard so far, reconnects included.
+    pub fn connections(&self) -> usize {
+        self.state.connections.load(Ordering::SeqCst)
+    }
+
+    // Sessions started by identifying so far.
+    pub fn sessions(&self) -> usize {
+        self.state.sessions.load(Ordering::SeqCst)
+    }
+
+    pub async fn next_payload(&self) -> Option<Value> {
+        self.received.lock().await.recv().await
+    }
+
+    pub async fn wait_for_op(&self, opcode: u8) -> Option<Value> {
+        loop {
+            let payload = self.next_payload().await?;
+            if payload["op"] == opcode {
+                return Some(payload);
+            }
+        }
+    }
+
+    // Resolves with the close code the client sent, or `None` if it dropped
+    // the connection without a close frame.
+    pub async fn wait_for_close(&self) -> Option<Option<u16>> {
+        self.closed.lock().await.recv().await
+    }
+
+    pub fn dispatch(&self, name: &str, data: Value) -> bool {
+        self.state.dispatch(name, data)
+    }
+
+    // Sends anything at all, malformed payloads included.
+    pub fn send(&self, payload: Value) -> bool {
+        self.state.send(payload)
+    }
+
+    pub fn request_reconnect(&self) -> bool {
+        self.state.send(json!({ "op": 7, "d": null }))
+    }
+
+    pub fn invalidate_session(&self, resumable: bool) -> bool {
+        self.state.send(json!({ "op": 9, "d": resumable }))
+    }
+
+    pub fn request_heartbeat(&self) -> bool {
+        self.state.send(json!({ "op": 1, "d": null }))
+    }
+
+    pub fn close(&self, code: u16) -> bool {
+        let current = self.state.current.lock().expect("Should not be poisoned");
+        let frame = CloseFrame {
+            code: CloseCode::from(code),
+            reason: "".into(),
+        };
+        current
+            .as_ref()
+            .is_some_and(|client| client.sender.send(Message::Close(Some(frame))).is_ok())
+    }
+}
+
+async fn accept_loop(listener: TcpListener, state: Arc<GatewayState>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(serve(stream, state.clone()));
+    }
+}
+
+async fn serve(stream: TcpStream, state: Arc<GatewayState>) {
+    let mut etf = false;
+    // The error type is tungstenite's to pick.
+    #[allow(clippy::result_large_err)]
+    let handshake = |request: &Request, response: Response| {
+        etf = request
+            .uri()
+            .query()
+            .is_some_and(|query| query.contains("encoding=etf"));
+        Ok::<_, ErrorResponse>(response)
+    };
+    let Ok(socket) = accept_hdr_async(stream, handshake).await else {
+        return;
+    };
+    state.connections.fetch_add(1, Ordering::SeqCst);
+    let (mut writer, mut reader) = socket.split();
+    let (sender, mut outgoing) = mpsc::unbounded_channel();
+    let client = Client { sender, etf };
+    *state.current.lock().expect("Should not be poisoned") = Some(client.clone());
+    tokio::spawn(async move {
+        while let Some(msg) = outgoing.recv().await {
+            let is_close = matches!(msg, Message::Close(_));
+            if writer.send(msg).await.is_err() || is_close {
+                break;
+            }
+        }
+    });
+
+    let hello = json!({ "op": 10, "d": { "heartbeat_interval": state.heartbeat_interval } });
+    client.send(&hello);
+
+    let mut close_code = None;
+    while let Some(Ok(msg)) = reader.next().await {
+        if let Message::Close(frame) = &msg {
+            close_code = frame.as_ref().map(|frame| frame.code.into());
+            break;
+        }
+        let Some(payload) = client.decode(&msg) else {
+            continue;
+        };
+        match payload["op"].as_u64() {
+            Some(1) => {
+                client.send(&json!({ "op": 11 }));
+            }
+            Some(2) => {
+                let session = state.sessions.fetch_add(1, Ordering::SeqCst);
+                let session_id = format!("mock-session-{}", session);
+                *state.session_id.lock().expect("Should not be poisoned") =
+                    Some(session_id.clone());
+                state.sequence.store(0, Ordering::SeqCst);
+                state.dispatch(
+                    "READY",
+                    json!({
+                        "v": 10,
+                        "user": state.user,
+                        "guilds": [],
+                        "session_id": session_id,
+                        // Bots are pointed at the mock with a forced gateway url,
+                        // which resuming has to keep using.
+                        "resume_gateway_url": "ws://127.0.0.1:9",
+                        "shard": payload["d"]["shard"],
+                    }),
+                );
+            }
+            Some(6) => {
+                let session_id = state
+                    .session_id
+                    .lock()
+                    .expect("Should not be poisoned")
+                    .clone();
+                if session_id.as_deref() == payload["d"]["session_id"].as_str() {
+                    state.dispatch("RESUMED", Value::Null);
+                } else {
+                    state.send(json!({ "op": 9, "d": false }));
+                }
+            }
+            _ => {}
+        }
+        let _ = state.received.send(payload);
+    }
+    let _ = state.closed.send(close_code);
+}
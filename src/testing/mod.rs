@@ -0,0 +1,127 @@
+mod gateway;
+mod rest;
+
+pub use gateway::*;
+pub use rest::*;
+
+use std::{fmt::Debug, io};
+
+use futures::Future;
+use serde_json::{json, Value};
+
+use crate::prelude::*;
+
+pub struct MockDiscord {
+    pub gateway: MockGateway,
+    pub rest: MockRest,
+}
+
+impl MockDiscord {
+    pub const TOKEN: &'static str = "mock-token";
+
+    pub async fn start() -> io::Result<Self> {
+        Self::with_heartbeat_interval(45_000).await
+    }
+
+    pub async fn with_user(user: Value) -> io::Result<Self> {
+        Self::start_with(user, 45_000).await
+    }
+
+    pub async fn with_heartbeat_interval(heartbeat_interval: u64) -> io::Result<Self> {
+        Self::start_with(
+            json!({ "id": "1000", "username": "mili" }),
+            heartbeat_interval,
+        )
+        .await
+    }
+
+    async fn start_with(user: Value, heartbeat_interval: u64) -> io::Result<Self> {
+        let gateway = MockGateway::start(user.clone(), heartbeat_interval).await?;
+        let rest = MockRest::start().await?;
+        rest.route(
+            "GET",
+            "/gateway/bot",
+            200,
+            json!({
+                "url": gateway.url(),
+                "shards": 1,
+                "session_start_limit": {
+                    "total": 1000,
+                    "remaining": 1000,
+                    "reset_after": 0,
+                    "max_concurrency": 1,
+                },
+            }),
+        );
+        rest.route("GET", "/users/@me", 200, user);
+        rest.route(
+            "GET",
+            "/applications/@me",
+            200,
+            json!({
+                "id": "1000",
+                "name": "mili",
+                "flags": (1 << 12) | (1 << 14) | (1 << 18),
+            }),
+        );
+        Ok(Self { gateway, rest })
+    }
+
+    pub fn template(&self) -> BotTemplate {
+        BotTemplate::default()
+            .api_url(self.rest.url())
+            .gateway_url(self.gateway.url())
+    }
+
+    pub fn token(&self) -> Token {
+        Token::insecure(Self::TOKEN)
+    }
+
+    // Sent by a user who is not a bot, `gateway.dispatch` covers the rest.
+    pub fn message_create(&self, id: &str, channel_id: &str, content: &str) -> bool {
+        self.gateway.dispatch(
+            "MESSAGE_CREATE",
+            json!({
+                "id": id,
+                "channel_id": channel_id,
+                "author": { "id": "3000", "username": "someone" },
+                "content": content,
+                "tts": false,
+            }),
+        )
+    }
+
+    // Starts `script` once the bot has identified and panics if the bot stops
+    // before the script is done.
+    pub async fn run_until<T, E>(
+        &self,
+        bot: impl Future<Output = Result<(), E>>,
+        script: impl Future<Output = T>,
+    ) -> T
+    where
+        E: Debug,
+    {
+        let script = async {
+            self.gateway.wait_for_op(2).await;
+            script.await
+        };
+        tokio::select! {
+            res = bot => panic!("Bot should keep running: {:?}", res.err()),
+            out = script => out,
+        }
+    }
+}
+
+// Drives paused time by hand for tests that also talk over sockets. Time only
+// moves in steps here, as a running blocking task keeps auto-advance off, and
+// every step leaves loopback traffic a moment of real time to arrive. Only
+// built for the crate's own tests, as `advance` needs tokio's `test-util`.
+#[cfg(test)]
+pub(crate) async fn step_paused_time(step: std::time::Duration) {
+    use std::time::Duration;
+
+    loop {
+        tokio::time::advance(step).await;
+        let _ = tokio::task::spawn_blocking(|| std::thread::sleep(Duration::from_millis(1))).await;
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+};
+
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedRequest {
+    pub method: Box<str>,
+    pub path: Box<str>,
+    pub body: Value,
+}
+
+type Routes = Mutex<HashMap<(Box<str>, Box<str>), (u16, Value)>>;
+
+struct RestState {
+    routes: Routes,
+    recorded: mpsc::UnboundedSender<RecordedRequest>,
+}
+
+pub struct MockRest {
+    url: Box<str>,
+    state: Arc<RestState>,
+    recorded: tokio::sync::Mutex<mpsc::UnboundedReceiver<RecordedRequest>>,
+}
+
+impl MockRest {
+    pub async fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/api", listener.local_addr()?).into();
+        let (sender, recver) = mpsc::unbounded_channel();
+        let state = Arc::new(RestState {
+            routes: Mutex::new(HashMap::new()),
+            recorded: sender,
+        });
+        tokio::spawn(accept_loop(listener, state.clone()));
+        Ok(Self {
+            url,
+            state,
+            recorded: tokio::sync::Mutex::new(recver),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // `path` is relative to the versioned API root, e.g. `/channels/1`.
+    pub fn route(&self, method: &str, path: &str, status: u16, body: Value) {
+        self.state
+            .routes
+            .lock()
+            .expect("Should not be poisoned")
+            .insert((method.into(), path.into()), (status, body));
+    }
+
+    pub async fn next_request(&self) -> Option<RecordedRequest> {
+        self.recorded.lock().await.recv().await
+    }
+
+    pub fn try_next_request(&self) -> Option<RecordedRequest> {
+        self.recorded.try_lock().ok()?.try_recv().ok()
+    }
+}
+
+async fn accept_loop(listener: TcpListener, state: Arc<RestState>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(serve(stream, state.clone()));
+    }
+}
+
+async fn serve(stream: TcpStream, state: Arc<RestState>) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    loop {
+        let mut request_line = String::new();
+        if stream.read_line(&mut request_line).await? == 0 {
+            return Ok(());
+        }
+        let mut parts = request_line.split_whitespace();
+        let method: Box<str> = parts.next().unwrap_or_default().into();
+        let target = parts.next().unwrap_or_default();
+
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            stream.read_line(&mut header).await?;
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        stream.read_exact(&mut body).await?;
+
+        let path = strip_version(target.split('?').next().unwrap_or_default());
+        let (status, response) = state
+            .routes
+            .lock()
+            .expect("Should not be poisoned")
+            .get(&(method.clone(), path.clone()))
+            .cloned()
+            .unwrap_or_else(|| (404, json!({ "message": "404: Not Found", "code": 0 })));
+        let _ = state.recorded.send(RecordedRequest {
+            method,
+            path,
+            body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+        });
+
+        let response = response.to_string();
+        let head = format!(
+            "HTTP/1.1 {} {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n",
+            status,
+            if status < 400 { "OK" } else { "ERROR" },
+            response.len()
+        );
+        let stream = stream.get_mut();
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(response.as_bytes()).await?;
+    }
+}
+
+// Turns `/api/v10/channels/1` into `/channels/1`.
+fn strip_version(target: &str) -> Box<str> {
+    let route = target.trim_start_matches("/api");
+    match route.strip_prefix("/v") {
+        Some(versioned) => {
+            let start = versioned.find('/').unwrap_or(versioned.len());
+            versioned[start..].into()
+        }
+        None => route.into(),
+    }
+}